@@ -0,0 +1,293 @@
+use std::f64::consts::PI;
+
+use gtk::cairo::{Context, Rectangle};
+use pangocairo::pango;
+
+use crate::axis::Axis;
+use crate::cairo_utils::{text_aligned, text_rect, TextPos};
+
+/// Geometry of an annotation, in data coordinates
+#[derive(Clone, Debug)]
+pub enum Shape {
+    /// horizontal line at y
+    HLine(f64),
+    /// vertical line at x
+    VLine(f64),
+    /// shaded band between two x values
+    XSpan(f64, f64),
+    /// shaded band between two y values
+    YSpan(f64, f64),
+    Rect {
+        x: (f64, f64),
+        y: (f64, f64),
+    },
+    Arrow {
+        from: (f64, f64),
+        to: (f64, f64),
+    },
+    Text {
+        pos: (f64, f64),
+        text: String,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct AnnotationStyle {
+    pub color: (f64, f64, f64),
+    pub line_width: f64,
+    /// dash pattern in pixels, empty for solid lines
+    pub dash: Vec<f64>,
+    /// opacity of the fill for spans and rectangles
+    pub fill_alpha: f64,
+}
+
+impl Default for AnnotationStyle {
+    fn default() -> Self {
+        Self {
+            color: (0.2, 0.2, 0.8),
+            line_width: 1.5,
+            dash: vec![],
+            fill_alpha: 0.15,
+        }
+    }
+}
+
+/// A shape drawn on top of the chart that follows zooming and panning
+#[derive(Clone, Debug)]
+pub struct Annotation {
+    pub shape: Shape,
+    pub style: AnnotationStyle,
+    pub label: Option<String>,
+}
+
+/// Maps data coordinates to pixel positions in the chart rectangle
+struct ChartMap<'a> {
+    chart: Rectangle,
+    x_axis: &'a Axis,
+    y_axis: &'a Axis,
+}
+
+impl ChartMap<'_> {
+    fn x(&self, x: f64) -> f64 {
+        self.chart.x() + self.chart.width() * self.x_axis.data_to_axis(x)
+    }
+    fn y(&self, y: f64) -> f64 {
+        self.chart.y() + self.chart.height() * (1.0 - self.y_axis.data_to_axis(y))
+    }
+    fn left(&self) -> f64 {
+        self.chart.x()
+    }
+    fn right(&self) -> f64 {
+        self.chart.x() + self.chart.width()
+    }
+    fn top(&self) -> f64 {
+        self.chart.y()
+    }
+    fn bottom(&self) -> f64 {
+        self.chart.y() + self.chart.height()
+    }
+}
+
+impl Annotation {
+    pub fn new(shape: Shape) -> Self {
+        Self {
+            shape,
+            style: AnnotationStyle::default(),
+            label: None,
+        }
+    }
+
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = Some(label.to_owned());
+        self
+    }
+
+    pub fn with_style(mut self, style: AnnotationStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Draw inside the chart rectangle (pixel coordinates), texts and labels in `font`
+    /// and the color of the annotation
+    pub fn draw(
+        &self,
+        cx: &Context,
+        chart: Rectangle,
+        x_axis: &Axis,
+        y_axis: &Axis,
+        font: &pango::FontDescription,
+    ) {
+        let map = ChartMap {
+            chart,
+            x_axis,
+            y_axis,
+        };
+        let (r, g, b) = self.style.color;
+        // text shapes are drawn right away
+        cx.set_source_rgb(r, g, b);
+        cx.set_line_width(self.style.line_width);
+        cx.set_dash(&self.style.dash, 0.0);
+
+        // anchor for the label (placed to the right of it)
+        let anchor = match &self.shape {
+            Shape::HLine(y) => {
+                cx.move_to(map.left(), map.y(*y));
+                cx.line_to(map.right(), map.y(*y));
+                (map.left(), map.y(*y) - 10.0)
+            }
+            Shape::VLine(x) => {
+                cx.move_to(map.x(*x), map.top());
+                cx.line_to(map.x(*x), map.bottom());
+                (map.x(*x), map.top() + 10.0)
+            }
+            Shape::XSpan(x0, x1) => {
+                cx.rectangle(
+                    map.x(*x0),
+                    map.top(),
+                    map.x(*x1) - map.x(*x0),
+                    map.bottom() - map.top(),
+                );
+                self.fill_preserve(cx);
+                (map.x(x0.min(*x1)), map.top() + 10.0)
+            }
+            Shape::YSpan(y0, y1) => {
+                cx.rectangle(
+                    map.left(),
+                    map.y(*y1),
+                    map.right() - map.left(),
+                    map.y(*y0) - map.y(*y1),
+                );
+                self.fill_preserve(cx);
+                (map.left(), map.y(y0.max(*y1)) + 10.0)
+            }
+            Shape::Rect { x, y } => {
+                cx.rectangle(
+                    map.x(x.0),
+                    map.y(y.1),
+                    map.x(x.1) - map.x(x.0),
+                    map.y(y.0) - map.y(y.1),
+                );
+                self.fill_preserve(cx);
+                (map.x(x.0.min(x.1)), map.y(y.0.max(y.1)) - 10.0)
+            }
+            Shape::Arrow { from, to } => {
+                let (x0, y0) = (map.x(from.0), map.y(from.1));
+                let (x1, y1) = (map.x(to.0), map.y(to.1));
+                cx.move_to(x0, y0);
+                cx.line_to(x1, y1);
+                // arrow head
+                let angle = (y1 - y0).atan2(x1 - x0);
+                for side in [-1.0, 1.0] {
+                    let a = angle + PI + side * PI / 7.0;
+                    cx.move_to(x1, y1);
+                    cx.line_to(x1 + 10.0 * a.cos(), y1 + 10.0 * a.sin());
+                }
+                (x0, y0)
+            }
+            Shape::Text { pos, text } => {
+                text_aligned(
+                    cx,
+                    (map.x(pos.0), map.y(pos.1)),
+                    text,
+                    TextPos::Right,
+                    font,
+                    0.0,
+                    false,
+                );
+                (map.x(pos.0), map.y(pos.1) + 14.0)
+            }
+        };
+        cx.set_source_rgb(r, g, b);
+        cx.stroke().unwrap();
+        cx.set_dash(&[], 0.0);
+
+        if let Some(label) = &self.label {
+            text_aligned(cx, anchor, label, TextPos::Right, font, 4.0, false);
+        }
+    }
+
+    fn fill_preserve(&self, cx: &Context) {
+        let (r, g, b) = self.style.color;
+        cx.set_source_rgba(r, g, b, self.style.fill_alpha);
+        cx.fill_preserve().unwrap();
+    }
+
+    /// Whether the pixel position (px, py) is on the annotation, within `tolerance` pixels.
+    /// Texts are hit on the box they are drawn in with `font`.
+    pub fn hit_test(
+        &self,
+        chart: Rectangle,
+        x_axis: &Axis,
+        y_axis: &Axis,
+        font: &pango::FontDescription,
+        (px, py): (f64, f64),
+        tolerance: f64,
+    ) -> bool {
+        let map = ChartMap {
+            chart,
+            x_axis,
+            y_axis,
+        };
+        let between =
+            |v: f64, a: f64, b: f64| v >= a.min(b) - tolerance && v <= a.max(b) + tolerance;
+        match &self.shape {
+            Shape::HLine(y) => (py - map.y(*y)).abs() <= tolerance,
+            Shape::VLine(x) => (px - map.x(*x)).abs() <= tolerance,
+            Shape::XSpan(x0, x1) => between(px, map.x(*x0), map.x(*x1)),
+            Shape::YSpan(y0, y1) => between(py, map.y(*y0), map.y(*y1)),
+            Shape::Rect { x, y } => {
+                between(px, map.x(x.0), map.x(x.1)) && between(py, map.y(y.0), map.y(y.1))
+            }
+            Shape::Arrow { from, to } => {
+                let (x0, y0) = (map.x(from.0), map.y(from.1));
+                let (dx, dy) = (map.x(to.0) - x0, map.y(to.1) - y0);
+                let l2 = dx * dx + dy * dy;
+                let proj = if l2 > 0.0 {
+                    (((px - x0) * dx + (py - y0) * dy) / l2).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                (px - x0 - proj * dx).hypot(py - y0 - proj * dy) <= tolerance
+            }
+            Shape::Text { pos, text } => {
+                let position = (map.x(pos.0), map.y(pos.1));
+                let r = text_rect(position, text, TextPos::Right, font, 0.0, false);
+                between(px, r.x(), r.x() + r.width()) && between(py, r.y(), r.y() + r.height())
+            }
+        }
+    }
+
+    /// Move by a distance relative to the chart size (works for log axes too)
+    pub fn drag(&mut self, x_axis: &Axis, y_axis: &Axis, dx_01: f64, dy_01: f64) {
+        let mx = |x: &mut f64| *x = x_axis.axis_to_data(x_axis.data_to_axis(*x) + dx_01);
+        let my = |y: &mut f64| *y = y_axis.axis_to_data(y_axis.data_to_axis(*y) + dy_01);
+        match &mut self.shape {
+            Shape::HLine(y) => my(y),
+            Shape::VLine(x) => mx(x),
+            Shape::XSpan(x0, x1) => {
+                mx(x0);
+                mx(x1);
+            }
+            Shape::YSpan(y0, y1) => {
+                my(y0);
+                my(y1);
+            }
+            Shape::Rect { x, y } => {
+                mx(&mut x.0);
+                mx(&mut x.1);
+                my(&mut y.0);
+                my(&mut y.1);
+            }
+            Shape::Arrow { from, to } => {
+                mx(&mut from.0);
+                mx(&mut to.0);
+                my(&mut from.1);
+                my(&mut to.1);
+            }
+            Shape::Text { pos, .. } => {
+                mx(&mut pos.0);
+                my(&mut pos.1);
+            }
+        }
+    }
+}
@@ -1,18 +1,27 @@
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::f64::consts::PI;
 
 use gtk::cairo::Context;
 
-use crate::axis::{Axis, AxisPlacement, AxisType};
-use crate::cairo_utils::PixelContext;
-use crate::grid::Grid;
+use crate::annotation::{Annotation, AnnotationStyle, Shape};
+use crate::axis::{Axis, AxisPlacement, AxisType, ScaleMode};
+use crate::cairo_utils::{format_number, text_aligned, Fonts, PixelContext, TextPos};
+use crate::colormap::Colorbar;
+use crate::grid::{Grid, GridAxis, GridLayer, GridTicks};
+use crate::image::{Image, ImagePoint};
+use crate::mask::{Mask, MaskResult};
+use crate::theme::Theme;
+use crate::trace::{
+    Baseline, ErrorStyle, Errors, FillStyle, Marker, ScatterStyle, SnapPoint, Trace, TraceKind,
+};
 
+/// Space around the chart in pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Margins {
     // TODO integers?
-    left: f64,
-    right: f64,
-    top: f64,
-    bottom: f64,
+    pub left: f64,
+    pub right: f64,
+    pub top: f64,
+    pub bottom: f64,
 }
 
 impl Default for Margins {
@@ -26,115 +35,71 @@ impl Default for Margins {
     }
 }
 
-pub struct Trace {
-    pub values: Vec<(f64, f64)>,
-    pub bbox: gtk::cairo::Rectangle,
-    #[allow(dead_code)]
-    pub name: String,
-}
+/// Room for the ticks and label of a secondary x axis, added to the top margin
+const SECONDARY_X_MARGIN: f64 = 40.0;
+/// Room for the ticks and label of a secondary y axis, added to the right margin
+const SECONDARY_Y_MARGIN: f64 = 55.0;
+/// Room around an Axes title, added to the top margin along with the font size
+const TITLE_PADDING: f64 = 6.0;
+/// Points drawn between checks whether drawing a trace was cancelled
+const CANCEL_CHECK_POINTS: usize = 4096;
 
-impl Trace {
-    pub fn new(values: Vec<(f64, f64)>, name: &str) -> Self {
-        let mut s = Self {
-            values,
-            bbox: gtk::cairo::Rectangle::new(0.0, 0.0, 1.0, 1.0),
-            name: name.to_owned(),
-        };
-        s.update_bbox();
-        s
-    }
-
-    fn update_bbox(&mut self) {
-        self.bbox = if self.values.len() < 2 {
-            gtk::cairo::Rectangle::new(0.0, 0.0, 1.0, 1.0)
-        } else {
-            let (xmin, xmax, ymin, ymax) = self.values.iter().fold(
-                (
-                    f64::INFINITY,
-                    f64::NEG_INFINITY,
-                    f64::INFINITY,
-                    f64::NEG_INFINITY,
-                ),
-                |(xmin, xmax, ymin, ymax), (x, y)| {
-                    (xmin.min(*x), xmax.max(*x), ymin.min(*y), ymax.max(*y))
-                },
-            );
-            gtk::cairo::Rectangle::new(xmin, ymin, xmax - xmin, ymax - ymin)
-        }
+/// " ± e" or " +a/-b" for a cursor readout, empty without errors
+fn format_error(error: Option<(f64, f64)>) -> String {
+    match error {
+        Some((minus, plus)) if minus == plus => format!(" ± {}", format_number(plus)),
+        Some((minus, plus)) => format!(" +{}/-{}", format_number(plus), format_number(minus)),
+        None => String::new(),
     }
+}
 
-    pub fn nearest_point(
-        &self,
-        t: f64,
-        y: f64,
-        tradius: f64,
-        yradius: f64,
-    ) -> Option<(f64, f64, f64)> {
-        // TODO: for log scale, transform values first before finding closest point
-        // or change the x, y arguments to this function to be relative (window) coords?
-
-        let segment_start =
-            self.values.partition_point(|(x, _)| *x < t - tradius).saturating_sub(2);
-        let segment_end =
-            (self.values.partition_point(|(x, _)| *x < t + tradius) + 2).min(self.values.len());
-
-        let values_norm: Vec<_> = self.values[segment_start..segment_end]
-            .iter()
-            .map(|(t, y)| (t / tradius, y / yradius))
-            .collect();
-
-        // normalized query point
-        let t_norm = t / tradius;
-        let y_norm = y / yradius;
-
-        let distances: Vec<_> = self.values[segment_start..segment_end]
-            .windows(2)
-            .map(|s| {
-                // normalized start point and segment vector:
-                let start_x = s[0].0 / tradius;
-                let start_y = s[0].1 / yradius;
-                let dx = (s[1].0 - s[0].0) / tradius;
-                let dy = (s[1].1 - s[0].1) / yradius;
-
-                // length squared of segment:
-                let l2 = dx * dx + dy * dy;
-
-                // normalized projection onto the segment
-                // (value between 0 and 1 means the projection lies on the segment)
-                let proj = ((t_norm - start_x) * dx + (y_norm - start_y) * dy) / l2;
-                let proj = proj.clamp(0.0, 1.0);
-
-                let nearest_x = start_x + proj * dx;
-                let nearest_y = start_y + proj * dy;
-
-                let dist_x = nearest_x - t_norm;
-                let dist_y = nearest_y - y_norm;
-
-                let distance = dist_x * dist_x + dist_y * dist_y;
-
-                let nearest_x = nearest_x * tradius;
-                let nearest_y = nearest_y * yradius;
-
-                (distance, nearest_x, nearest_y)
-            })
-            .collect();
-
-        distances
-            .into_iter()
-            .min_by(|a, b| a.0.total_cmp(&b.0))
-            .filter(|(d, _, _)| *d < 1.0)
+/// Fill the current path and draw its outline according to the fill style
+fn fill_and_stroke(cx: &Context, fill: &FillStyle, trace_color: (f64, f64, f64)) {
+    let (r, g, b) = fill.color.unwrap_or(trace_color);
+    cx.set_source_rgba(r, g, b, fill.alpha);
+    if fill.edge_width > 0.0 {
+        cx.fill_preserve().unwrap();
+        let (r, g, b) = fill.edge_color.unwrap_or(trace_color);
+        cx.set_source_rgb(r, g, b);
+        cx.set_line_width(fill.edge_width);
+        cx.stroke().unwrap();
+    } else {
+        cx.fill().unwrap();
     }
 }
 
 pub struct Axes {
-    pub primary_x: Rc<RefCell<Axis>>,
-    pub primary_y: Rc<RefCell<Axis>>,
+    /// shown above the chart (in label markup), see `set_title`
+    pub title: Option<String>,
+    pub primary_x: Axis,
+    pub primary_y: Axis,
+    /// scale along the top edge of the chart, see `set_secondary_x`
+    pub secondary_x: Option<Axis>,
+    /// scale along the right edge of the chart, see `set_secondary_y`
+    pub secondary_y: Option<Axis>,
     pub grid: Grid,
-
+    /// space around the chart for the axes, room for the title, colorbar and secondary
+    /// axes is added to it
     pub margins: Margins,
 
     pub traces: Vec<Trace>,
-    pub cursor: Option<(f64, f64)>,
+    pub images: Vec<Image>,
+    pub cursor: Option<SnapPoint>,
+    pub image_cursor: Option<ImagePoint>,
+    /// time linked from the cursor of an XY Axes, drawn as a vertical line
+    pub time_cursor: Option<f64>,
+    pub colorbar: Option<Colorbar>,
+    /// show a legend with one entry per trace in the top right corner of the chart
+    pub legend: bool,
+    pub fonts: Fonts,
+    pub theme: Theme,
+    pub annotations: Vec<Annotation>,
+    /// markers for the last measurement, replaced on every measurement
+    pub measurement_annotations: Vec<Annotation>,
+    /// limit lines or region all traces are tested against
+    pub mask: Option<Mask>,
+    /// the A and B markers, see `set_markers`
+    markers: Option<[Annotation; 2]>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -146,84 +111,214 @@ pub enum AxesCursorPosition {
 }
 
 impl Axes {
-    pub fn new(primary_x: Rc<RefCell<Axis>>, primary_y: Rc<RefCell<Axis>>) -> Self {
+    pub fn new(primary_x: Axis, primary_y: Axis) -> Self {
         Self {
+            title: None,
             primary_x,
             primary_y,
-            grid: Grid {},
+            secondary_x: None,
+            secondary_y: None,
+            grid: Grid::default(),
             margins: Margins::default(),
             traces: vec![],
+            images: vec![],
             cursor: None,
+            image_cursor: None,
+            time_cursor: None,
+            colorbar: None,
+            legend: false,
+            fonts: Fonts::default(),
+            theme: Theme::default(),
+            annotations: vec![],
+            measurement_annotations: vec![],
+            mask: None,
+            markers: None,
         }
     }
 
-    pub fn linear(shared_x: Option<Rc<RefCell<Axis>>>) -> Rc<RefCell<Self>> {
-        let primary_x = match shared_x {
-            Some(axis) => axis,
-            None => Rc::new(RefCell::new(Axis::new(
-                AxisPlacement::Bottom,
-                AxisType::Lin,
-                (-1.0, 1.0),
-            ))),
+    pub fn linear() -> Self {
+        Self::new(
+            Axis::new(AxisPlacement::Bottom, AxisType::Lin, (-1.0, 1.0)),
+            Axis::new(AxisPlacement::Left, AxisType::Lin, (0.0, 1.0)),
+        )
+    }
+
+    pub fn semilogx() -> Self {
+        Self::new(
+            Axis::new(AxisPlacement::Bottom, AxisType::Log, (0.1, 1.0)),
+            Axis::new(AxisPlacement::Left, AxisType::Lin, (0.0, 1.0)),
+        )
+    }
+
+    pub fn semilogy() -> Self {
+        Self::new(
+            Axis::new(AxisPlacement::Bottom, AxisType::Lin, (-1.0, 1.0)),
+            Axis::new(AxisPlacement::Left, AxisType::Log, (0.1, 1.0)),
+        )
+    }
+
+    pub fn add_trace(&mut self, mut t: Trace) {
+        // the first color-mapped scatter trace gets a colorbar
+        if let TraceKind::Scatter(style) = &t.kind {
+            if let Some((channel, colormap)) = &style.color_by {
+                if self.colorbar.is_none() {
+                    let range = t.channel_range(channel).unwrap_or((0.0, 1.0));
+                    self.set_colorbar(Some(Colorbar::new(*colormap, range, channel)));
+                }
+            }
+        }
+        t.set_scales((self.primary_x.axis_type(), self.primary_y.axis_type()));
+        self.traces.push(t);
+        self.autoscale();
+    }
+
+    /// Add an image, it gets a colorbar unless the Axes already has one
+    pub fn add_image(&mut self, image: Image) {
+        if self.colorbar.is_none() {
+            let colorbar = Colorbar::new(image.colormap, image.value_range, &image.name);
+            self.set_colorbar(Some(colorbar));
+        }
+        self.images.push(image);
+        self.autoscale();
+    }
+
+    /// Show a title above the chart (or remove it with None)
+    pub fn set_title(&mut self, title: Option<&str>) {
+        self.title = title.map(str::to_owned);
+    }
+
+    /// Show a colorbar in the right margin (or remove it with None)
+    pub fn set_colorbar(&mut self, colorbar: Option<Colorbar>) {
+        self.colorbar = colorbar;
+    }
+
+    /// Show a second x scale along the top of the chart (or remove it with None),
+    /// created with `AxisPlacement::Top`. Its range is independent of the primary x
+    /// axis; traces are always drawn against the primary axes.
+    pub fn set_secondary_x(&mut self, axis: Option<Axis>) {
+        self.secondary_x = axis;
+    }
+
+    /// Show a second y scale along the right of the chart (or remove it with None),
+    /// created with `AxisPlacement::Right`. Its range is independent of the primary y
+    /// axis; traces are always drawn against the primary axes.
+    pub fn set_secondary_y(&mut self, axis: Option<Axis>) {
+        self.secondary_y = axis;
+    }
+
+    /// `margins` plus the room for title, colorbar and secondary axes
+    fn chart_margins(&self) -> Margins {
+        let mut margins = self.margins;
+        if let Some(colorbar) = &self.colorbar {
+            margins.right += colorbar.margin();
+        }
+        if self.secondary_y.is_some() {
+            margins.right += SECONDARY_Y_MARGIN;
+        }
+        if self.secondary_x.is_some() {
+            margins.top += SECONDARY_X_MARGIN;
+        }
+        if self.title.is_some() {
+            margins.top += self.fonts.title + TITLE_PADDING;
+        }
+        margins
+    }
+
+    /// The axes whose ticks the grid lines follow
+    fn grid_axes(&self) -> (&Axis, &Axis) {
+        let x = match (self.grid.x_axis, &self.secondary_x) {
+            (GridAxis::Secondary, Some(axis)) => axis,
+            _ => &self.primary_x,
         };
-        Rc::new(RefCell::new(Self {
-            primary_x,
-            primary_y: Rc::new(RefCell::new(Axis::new(
-                AxisPlacement::Left,
-                AxisType::Lin,
-                (0.0, 1.0),
-            ))),
-            grid: Grid {},
-            margins: Margins::default(),
-            traces: vec![],
-            cursor: None,
-        }))
-    }
-
-    pub fn semilogx() -> Rc<RefCell<Self>> {
-        Rc::new(RefCell::new(Self {
-            primary_x: Rc::new(RefCell::new(Axis::new(
-                AxisPlacement::Bottom,
-                AxisType::Log,
-                (0.1, 1.0),
-            ))),
-            primary_y: Rc::new(RefCell::new(Axis::new(
-                AxisPlacement::Left,
-                AxisType::Lin,
-                (0.0, 1.0),
-            ))),
-            grid: Grid {},
-            margins: Margins::default(),
-            traces: vec![],
-            cursor: None,
-        }))
+        let y = match (self.grid.y_axis, &self.secondary_y) {
+            (GridAxis::Secondary, Some(axis)) => axis,
+            _ => &self.primary_y,
+        };
+        (x, y)
+    }
+
+    pub fn add_annotation(&mut self, annotation: Annotation) {
+        self.annotations.push(annotation);
     }
 
-    pub fn semilogy(shared_x: Option<Rc<RefCell<Axis>>>) -> Rc<RefCell<Self>> {
-        let primary_x = match shared_x {
-            Some(axis) => axis,
-            None => Rc::new(RefCell::new(Axis::new(
-                AxisPlacement::Bottom,
-                AxisType::Lin,
-                (-1.0, 1.0),
-            ))),
+    /// Show the A and B markers at these x values: two vertical lines the user can drag,
+    /// measurements are limited to the range between them. None removes them.
+    pub fn set_markers(&mut self, markers: Option<(f64, f64)>) {
+        let style = AnnotationStyle {
+            color: self.theme.cursor,
+            dash: vec![6.0, 3.0],
+            ..AnnotationStyle::default()
         };
-        Rc::new(RefCell::new(Self {
-            primary_x,
-            primary_y: Rc::new(RefCell::new(Axis::new(
-                AxisPlacement::Left,
-                AxisType::Log,
-                (0.1, 1.0),
-            ))),
-            grid: Grid {},
-            margins: Margins::default(),
-            traces: vec![],
-            cursor: None,
-        }))
+        self.markers = markers.map(|(a, b)| {
+            [(a, "A"), (b, "B")].map(|(x, label)| {
+                Annotation::new(Shape::VLine(x))
+                    .with_label(label)
+                    .with_style(style.clone())
+            })
+        });
     }
 
-    pub fn add_trace(&mut self, t: Trace) {
-        self.traces.push(t);
+    /// x range between the A and B markers, None if they aren't shown
+    pub fn marker_range(&self) -> Option<(f64, f64)> {
+        let x = |marker: &Annotation| match marker.shape {
+            Shape::VLine(x) => Some(x),
+            _ => None,
+        };
+        let [a, b] = self.markers.as_ref()?;
+        let (a, b) = (x(a)?, x(b)?);
+        Some((a.min(b), a.max(b)))
+    }
+
+    /// Annotations the user can drag: `annotations`, then the A and B markers
+    fn draggable(&self) -> impl Iterator<Item = &Annotation> {
+        self.annotations.iter().chain(self.markers.iter().flatten())
+    }
+
+    /// Result of testing each trace against the mask, empty if there is no mask
+    pub fn mask_results(&self) -> Vec<MaskResult> {
+        match &self.mask {
+            Some(mask) => self.traces.iter().map(|t| mask.check(t)).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Chart area (without margins) for the given Axes area, in pixel coordinates
+    pub fn chart_rect(&self, rect: gtk::cairo::Rectangle) -> gtk::cairo::Rectangle {
+        let margins = self.chart_margins();
+        gtk::cairo::Rectangle::new(
+            rect.x() + margins.left,
+            rect.y() + margins.top,
+            rect.width() - margins.left - margins.right,
+            rect.height() - margins.top - margins.bottom,
+        )
+    }
+
+    /// Topmost annotation at pixel position (x, y), counting `annotations` first and then
+    /// the A and B markers
+    pub fn annotation_at(&self, rect: gtk::cairo::Rectangle, x: f64, y: f64) -> Option<usize> {
+        let chart = self.chart_rect(rect);
+        let font = self.fonts.font(self.fonts.annotation, false);
+        let draggable: Vec<_> = self.draggable().collect();
+        draggable
+            .iter()
+            .rposition(|a| a.hit_test(chart, &self.primary_x, &self.primary_y, &font, (x, y), 4.0))
+    }
+
+    /// Move an annotation (counted as in `annotation_at`) by a pixel distance
+    pub fn drag_annotation(&mut self, rect: gtk::cairo::Rectangle, index: usize, dx: f64, dy: f64) {
+        let chart = self.chart_rect(rect);
+        let mut draggable = self
+            .annotations
+            .iter_mut()
+            .chain(self.markers.iter_mut().flatten());
+        if let Some(a) = draggable.nth(index) {
+            a.drag(
+                &self.primary_x,
+                &self.primary_y,
+                dx / chart.width(),
+                -dy / chart.height(),
+            );
+        }
     }
 
     pub fn cursor_position(
@@ -232,10 +327,9 @@ impl Axes {
         x: f64,
         y: f64,
     ) -> AxesCursorPosition {
-        let chart_width = rect.width() - self.margins.left - self.margins.right;
-        let chart_height = rect.height() - self.margins.top - self.margins.bottom;
-        let x_01 = (x - rect.x() - self.margins.left) / chart_width;
-        let y_01 = (y - rect.y() - self.margins.top) / chart_height;
+        let chart = self.chart_rect(rect);
+        let x_01 = (x - chart.x()) / chart.width();
+        let y_01 = (y - chart.y()) / chart.height();
 
         if 0.0 <= x_01 && x_01 <= 1.0 && 0.0 <= y_01 && y_01 <= 1.0 {
             AxesCursorPosition::Chart(x_01, y_01)
@@ -248,75 +342,198 @@ impl Axes {
         }
     }
 
-    pub fn snap_cursor(&self, pos: AxesCursorPosition) -> Option<(f64, f64)> {
+    pub fn snap_cursor(&self, pos: AxesCursorPosition) -> Option<SnapPoint> {
         match pos {
             AxesCursorPosition::Chart(x, y) => {
-                let data_x = self.primary_x.borrow().axis_to_data(x);
-                let data_y = self.primary_y.borrow().axis_to_data(1.0 - y);
-                let xrange = self.primary_x.borrow().range;
-                let yrange = self.primary_y.borrow().range;
-                let result = self
-                    .traces
+                let data_x = self.primary_x.axis_to_data(x);
+                let data_y = self.primary_y.axis_to_data(1.0 - y);
+                let scales = (self.primary_x.axis_type(), self.primary_y.axis_type());
+                // span of the axes on their scales
+                let span = |axis: &Axis| {
+                    let (start, end) = axis.range();
+                    let scale = axis.axis_type();
+                    scale.data_to_scale(end) - scale.data_to_scale(start)
+                };
+                let radius = (span(&self.primary_x) / 20.0, span(&self.primary_y) / 10.0);
+                self.traces
                     .iter()
-                    .map(|t| {
-                        t.nearest_point(
-                            data_x,
-                            data_y,
-                            (xrange.1 - xrange.0) / 20.0,
-                            (yrange.1 - yrange.0) / 10.0,
-                        )
+                    .enumerate()
+                    .filter_map(|(i, t)| {
+                        t.nearest_point(data_x, data_y, radius, scales)
+                            .map(|(d, index, x, y)| (d, i, index, x, y))
                     })
-                    .flatten()
                     .min_by(|a, b| a.0.total_cmp(&b.0))
-                    .map(|(_, x, y)| (x, y));
-
-                if let Some((resx, resy)) = result {
-                    // println!("Snap {data_x:.3} {data_y:.3} to {resx:.3} {resy:.3}");
-                }
-                result
+                    .map(|(_, trace, index, x, y)| {
+                        let (x_error, y_error) = self.traces[trace].errors_at(index);
+                        SnapPoint {
+                            trace,
+                            index,
+                            x,
+                            y,
+                            channels: self.traces[trace].channel_values(index),
+                            x_error,
+                            y_error,
+                        }
+                    })
             }
             _ => None,
         }
     }
 
-    pub fn zoom_fit(&mut self) {
-        if self.traces.len() > 0 {
-            let (xmin, xmax, ymin, ymax) = self.traces.iter().fold(
-                (
-                    f64::INFINITY,
-                    f64::NEG_INFINITY,
-                    f64::INFINITY,
-                    f64::NEG_INFINITY,
-                ),
-                |(xmin, xmax, ymin, ymax), tr| {
-                    let r = tr.bbox;
-                    (
-                        xmin.min(r.x()),
-                        xmax.max(r.x() + r.width()),
-                        ymin.min(r.y()),
-                        ymax.max(r.y() + r.height()),
-                    )
-                },
+    /// Topmost image cell under the cursor
+    pub fn image_value_at(&self, pos: AxesCursorPosition) -> Option<ImagePoint> {
+        let AxesCursorPosition::Chart(x, y) = pos else {
+            return None;
+        };
+        let data_x = self.primary_x.axis_to_data(x);
+        let data_y = self.primary_y.axis_to_data(1.0 - y);
+        self.images.iter().enumerate().rev().find_map(|(i, img)| {
+            let (col, row) = img.cell_at(data_x, data_y)?;
+            Some(ImagePoint {
+                image: i,
+                col,
+                row,
+                x: data_x,
+                y: data_y,
+                value: img.value(col, row),
+            })
+        })
+    }
+
+    /// Text describing the snapped cursor, one entry per line
+    pub fn cursor_readout(&self) -> Vec<String> {
+        let mut lines = vec![];
+        if let Some(snap) = &self.cursor {
+            let mut line = format!(
+                "{}: x = {}{}, y = {}{}",
+                self.traces[snap.trace].name,
+                format_number(snap.x),
+                format_error(snap.x_error),
+                format_number(snap.y),
+                format_error(snap.y_error)
             );
-            self.primary_x.borrow_mut().range = (xmin, xmax);
-            self.primary_y.borrow_mut().range = (ymin, ymax);
+            for (name, v) in &snap.channels {
+                line += &format!(", {name} = {}", format_number(*v));
+            }
+            lines.push(line);
+        }
+        if let Some(p) = &self.image_cursor {
+            lines.push(format!(
+                "{}: x = {}, y = {}, value = {}",
+                self.images[p.image].name,
+                format_number(p.x),
+                format_number(p.y),
+                format_number(p.value)
+            ));
+        }
+        lines
+    }
+
+    /// Whether any trace or image has points that can be shown
+    pub fn has_data(&self) -> bool {
+        self.data_bbox().is_some()
+    }
+
+    /// Bounding box of all traces and images, or None if there is no data
+    fn data_bbox(&self) -> Option<(f64, f64, f64, f64)> {
+        let trace_boxes = self.traces.iter().filter_map(|tr| tr.bbox);
+        let image_boxes = self.images.iter().map(|img| img.bbox());
+        trace_boxes.chain(image_boxes).fold(None, |acc, r| {
+            let (xmin, xmax, ymin, ymax) = acc.unwrap_or((
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+            ));
+            Some((
+                xmin.min(r.x()),
+                xmax.max(r.x() + r.width()),
+                ymin.min(r.y()),
+                ymax.max(r.y() + r.height()),
+            ))
+        })
+    }
+
+    /// y range of all trace points inside the current x range
+    fn visible_y_range(&self) -> Option<(f64, f64)> {
+        let (x0, x1) = self.primary_x.range();
+        self.traces
+            .iter()
+            .filter_map(|t| t.y_range_within(x0, x1))
+            .reduce(|(amin, amax), (bmin, bmax)| (amin.min(bmin), amax.max(bmax)))
+    }
+
+    pub fn zoom_fit(&mut self) {
+        if let Some((xmin, xmax, ymin, ymax)) = self.data_bbox() {
+            self.primary_x.fit(xmin, xmax);
+            self.primary_y.fit(ymin, ymax);
+        }
+    }
+
+    /// Fit only the y axis, to the data inside the current x range
+    pub fn zoom_fit_y(&mut self) {
+        if let Some((ymin, ymax)) = self.visible_y_range() {
+            self.primary_y.fit(ymin, ymax);
+        }
+    }
+
+    /// Re-fit the axes in `Auto` mode, call whenever the data changes
+    pub fn autoscale(&mut self) {
+        if let Some((xmin, xmax, ymin, ymax)) = self.data_bbox() {
+            let x_mode = self.primary_x.scale_mode;
+            match x_mode {
+                ScaleMode::Auto => self.primary_x.fit(xmin, xmax),
+                ScaleMode::Follow(_) => self.primary_x.follow(xmax),
+                _ => {}
+            }
+            if self.primary_y.scale_mode == ScaleMode::Auto {
+                self.primary_y.fit(ymin, ymax);
+            }
+        }
+        self.autoscale_visible();
+    }
+
+    /// Re-fit the y axis in `AutoVisible` mode, call whenever the x range changes
+    pub fn autoscale_visible(&mut self) {
+        if self.primary_y.scale_mode == ScaleMode::AutoVisible {
+            self.zoom_fit_y();
         }
     }
 
     pub fn zoom_at(&mut self, position: AxesCursorPosition, scale: f64) {
         match position {
             AxesCursorPosition::Chart(x, y) => {
-                self.primary_x.borrow_mut().zoom_at(x, scale);
-                self.primary_y.borrow_mut().zoom_at(1.0 - y, scale);
+                self.primary_x.zoom_at(x, scale);
+                self.primary_y.zoom_at(1.0 - y, scale);
             }
             AxesCursorPosition::XAxis(x) => {
-                self.primary_x.borrow_mut().zoom_at(x, scale);
+                self.primary_x.zoom_at(x, scale);
             }
             AxesCursorPosition::YAxis(y) => {
-                self.primary_y.borrow_mut().zoom_at(1.0 - y, scale);
+                self.primary_y.zoom_at(1.0 - y, scale);
+            }
+            AxesCursorPosition::None => {}
+        }
+        self.autoscale_visible();
+    }
+
+    /// Pan by a distance relative to the chart size (positive `dx` moves the view right,
+    /// positive `dy` moves it up)
+    pub fn pan(&mut self, position: AxesCursorPosition, dx: f64, dy: f64) {
+        match position {
+            AxesCursorPosition::Chart(..) => {
+                self.primary_x.pan(dx);
+                self.primary_y.pan(dy);
+            }
+            AxesCursorPosition::XAxis(_) => {
+                self.primary_x.pan(dx);
+            }
+            AxesCursorPosition::YAxis(_) => {
+                self.primary_y.pan(dy);
             }
             AxesCursorPosition::None => {}
         }
+        self.autoscale_visible();
     }
 
     /// Draw to a Cairo context
@@ -326,26 +543,67 @@ impl Axes {
         // pixel coordinates for the full Axes area (including margins):
         rect: gtk::cairo::Rectangle,
     ) {
-        let ll = (
-            rect.x() + self.margins.left,
-            rect.y() + rect.height() - self.margins.bottom,
-        );
-        let width = rect.width() - self.margins.left - self.margins.right;
-        let height = rect.height() - self.margins.bottom - self.margins.top;
-        self.primary_x.borrow().draw(cx, ll, width);
-        self.primary_y.borrow().draw(cx, ll, height);
-
-        self.grid.draw(
-            cx,
-            gtk::cairo::Rectangle::new(
-                rect.x() + self.margins.left,
-                rect.y() + self.margins.top,
-                width,
-                height,
-            ),
-            &self.primary_x.borrow(),
-            &self.primary_y.borrow(),
-        );
+        let grid_ticks = self.draw_background(cx, rect);
+        self.draw_traces(cx, rect, &grid_ticks, |cx, chart| {
+            for i in 0..self.traces.len() {
+                self.draw_trace(cx, chart, i, &|| false);
+            }
+        });
+        self.draw_overlay(cx, rect);
+    }
+
+    /// Draw the parts that only change with the axis ranges: chart area, axes, grid (if
+    /// below the traces) and colorbar. The area around the chart is left to the caller.
+    /// Returns the ticks of the grid axes, for a grid above the traces.
+    pub fn draw_background(&self, cx: &Context, rect: gtk::cairo::Rectangle) -> GridTicks {
+        let chart = self.chart_rect(rect);
+        let ll = (chart.x(), chart.y() + chart.height());
+        let (r, g, b) = self.theme.chart;
+        cx.set_source_rgb(r, g, b);
+        cx.rectangle(chart.x(), chart.y(), chart.width(), chart.height());
+        cx.fill().unwrap();
+
+        let (fonts, theme) = (&self.fonts, &self.theme);
+        let x_ticks = self.primary_x.draw(cx, ll, chart.width(), fonts, theme);
+        let y_ticks = self.primary_y.draw(cx, ll, chart.height(), fonts, theme);
+        let secondary_x = self.secondary_x.as_ref().map(|axis| {
+            let ul = (chart.x(), chart.y());
+            (axis, axis.draw(cx, ul, chart.width(), fonts, theme))
+        });
+        let secondary_y = self.secondary_y.as_ref().map(|axis| {
+            let lr = (chart.x() + chart.width(), chart.y() + chart.height());
+            (axis, axis.draw(cx, lr, chart.height(), fonts, theme))
+        });
+
+        // the same axes as `grid_axes`, with the ticks just drawn
+        let grid_ticks = GridTicks {
+            x: match (self.grid.x_axis, secondary_x) {
+                (GridAxis::Secondary, Some((_, ticks))) => ticks,
+                _ => x_ticks,
+            },
+            y: match (self.grid.y_axis, secondary_y) {
+                (GridAxis::Secondary, Some((_, ticks))) => ticks,
+                _ => y_ticks,
+            },
+        };
+        if self.grid.layer == GridLayer::Below {
+            let (x_axis, y_axis) = self.grid_axes();
+            let (x, y) = ((x_axis, &grid_ticks.x), (y_axis, &grid_ticks.y));
+            self.grid.draw(cx, chart, x, y, theme);
+        }
+
+        let (r, g, b) = self.theme.text;
+        cx.set_source_rgb(r, g, b);
+        if let Some(title) = &self.title {
+            let top_center = (chart.x() + chart.width() / 2.0, chart.y());
+            let font = self.fonts.font(self.fonts.title, true);
+            let spacing = if self.secondary_x.is_some() {
+                3.0 + SECONDARY_X_MARGIN
+            } else {
+                3.0
+            };
+            text_aligned(cx, top_center, title, TextPos::Top, &font, spacing, false);
+        }
 
         if false {
             cx.set_line_width(1.0);
@@ -359,59 +617,550 @@ impl Axes {
             cx.stroke().unwrap();
         }
 
-        // draw the traces
-        cx.rectangle(ll.0, ll.1, width, -height);
-        cx.clip();
-        for (i, t) in self.traces.iter().enumerate() {
-            if t.values.len() > 0 {
-                cx.move_to(
-                    self.margins.left + width * self.primary_x.borrow().data_to_axis(t.values[0].0),
-                    rect.y()
-                        + self.margins.top
-                        + height * (1.0 - self.primary_y.borrow().data_to_axis(t.values[0].1)),
-                );
-            }
-            for (x, y) in &t.values[1..] {
-                cx.line_to(
-                    self.margins.left + width * self.primary_x.borrow().data_to_axis(*x),
-                    rect.y()
-                        + self.margins.top
-                        + height * (1.0 - self.primary_y.borrow().data_to_axis(*y)),
-                );
+        if let Some(colorbar) = &self.colorbar {
+            let mut x = chart.x() + chart.width() + self.margins.right;
+            if self.secondary_y.is_some() {
+                x += SECONDARY_Y_MARGIN;
             }
+            let bar = gtk::cairo::Rectangle::new(x, chart.y(), 0.0, chart.height());
+            colorbar.draw(cx, bar, &self.fonts, &self.theme);
+        }
+        grid_ticks
+    }
 
-            cx.identity_matrix();
-            cx.set_line_width(2.0);
-            cx.set_source_rgb(1.0 - 0.1 * (i as f64), 0.6 + 0.2 * (i as f64), 0.0);
-            cx.stroke().unwrap();
+    /// Draw the images, then the traces through `traces`, given the chart rectangle with
+    /// the clip already set (`draw_trace` for each trace, or e.g. a frame rendered in the
+    /// background), then the grid if it is above the traces, at the `grid_ticks` returned
+    /// by `draw_background`.
+    pub fn draw_traces(
+        &self,
+        cx: &Context,
+        rect: gtk::cairo::Rectangle,
+        grid_ticks: &GridTicks,
+        traces: impl FnOnce(&Context, gtk::cairo::Rectangle),
+    ) {
+        let chart = self.chart_rect(rect);
+        cx.rectangle(chart.x(), chart.y(), chart.width(), chart.height());
+        cx.clip();
+        for img in &self.images {
+            img.draw(cx, chart, &self.primary_x, &self.primary_y);
+        }
+        traces(cx, chart);
+        if self.grid.layer == GridLayer::Above {
+            let (x_axis, y_axis) = self.grid_axes();
+            let (x, y) = ((x_axis, &grid_ticks.x), (y_axis, &grid_ticks.y));
+            self.grid.draw(cx, chart, x, y, &self.theme);
+        }
+        cx.reset_clip();
+    }
+
+    /// Draw what changes with user interaction on top of the traces: annotations, mask,
+    /// cursors, chart outline, legend and cursor readout
+    pub fn draw_overlay(&self, cx: &Context, rect: gtk::cairo::Rectangle) {
+        let chart = self.chart_rect(rect);
+        let (width, height) = (chart.width(), chart.height());
+        let ll = (chart.x(), chart.y() + height);
+
+        cx.rectangle(chart.x(), chart.y(), width, height);
+        cx.clip();
+        let font = self.fonts.font(self.fonts.annotation, false);
+        for a in self.draggable().chain(&self.measurement_annotations) {
+            a.draw(cx, chart, &self.primary_x, &self.primary_y, &font);
+        }
+        if let Some(mask) = &self.mask {
+            self.draw_mask(cx, chart, mask);
         }
         cx.reset_clip();
 
         cx.set_line_width(1.0);
-        cx.set_source_rgb(0.0, 0.0, 0.0);
+        let (r, g, b) = self.theme.cursor;
+        cx.set_source_rgb(r, g, b);
 
-        if let Some((x, y)) = self.cursor {
-            let px_x = self.margins.left + width * self.primary_x.borrow().data_to_axis(x);
-            let px_y = rect.y()
-                + self.margins.top
-                + height * (1.0 - self.primary_y.borrow().data_to_axis(y));
+        if let Some(SnapPoint { x, y, .. }) = self.cursor {
+            let (px_x, px_y) = self.data_to_pixel(chart, (x, y));
 
             cx.set_dash(&[5.0], 0.0);
             // vertical line
-            PixelContext::new(cx).move_to(px_x, rect.y() + self.margins.top);
-            PixelContext::new(cx).line_to(px_x, rect.y() + rect.height() - self.margins.bottom);
+            PixelContext::new(cx).move_to(px_x, chart.y());
+            PixelContext::new(cx).line_to(px_x, chart.y() + height);
             // horizontal line
-            PixelContext::new(cx).move_to(self.margins.left, px_y);
-            PixelContext::new(cx).line_to(rect.width() - self.margins.right, px_y);
+            PixelContext::new(cx).move_to(chart.x(), px_y);
+            PixelContext::new(cx).line_to(chart.x() + width, px_y);
+            cx.stroke().unwrap();
+            cx.set_dash(&[], 0.0);
+        }
+
+        if let Some(t) = self.time_cursor {
+            let px_x = chart.x() + width * self.primary_x.data_to_axis(t);
+            let (r, g, b) = self.theme.time_cursor;
+            cx.set_source_rgb(r, g, b);
+            cx.set_dash(&[2.0, 3.0], 0.0);
+            PixelContext::new(cx).move_to(px_x, chart.y());
+            PixelContext::new(cx).line_to(px_x, chart.y() + height);
             cx.stroke().unwrap();
             cx.set_dash(&[], 0.0);
         }
 
         // chart area outline
         cx.set_line_width(1.0);
-        cx.set_source_rgb(0.0, 0.0, 0.0);
+        let (r, g, b) = self.theme.axis;
+        cx.set_source_rgb(r, g, b);
         PixelContext::new(cx).rectangle(ll.0, ll.1, width, -height);
         cx.stroke().unwrap();
+
+        if self.legend {
+            self.draw_legend(cx, chart);
+        }
+
+        let line_height = 1.35 * self.fonts.annotation;
+        let (r, g, b) = self.theme.text;
+        cx.set_source_rgb(r, g, b);
+        for (i, line) in self.cursor_readout().iter().enumerate() {
+            let y = chart.y() + line_height * (i as f64 + 0.75);
+            text_aligned(cx, (chart.x(), y), line, TextPos::Right, &font, 5.0, false);
+        }
+    }
+
+    /// Draw the trace at `index` (with its error bars) into the chart rectangle. Lines
+    /// and scatter traces stop halfway once `cancelled` returns true, it is checked
+    /// every `CANCEL_CHECK_POINTS` points.
+    pub fn draw_trace(
+        &self,
+        cx: &Context,
+        chart: gtk::cairo::Rectangle,
+        index: usize,
+        cancelled: &dyn Fn() -> bool,
+    ) {
+        let t = &self.traces[index];
+        let color = self.theme.trace_color(index);
+        if let Some(errors) = &t.errors {
+            self.draw_errors(cx, chart, t, errors, color);
+        }
+        match &t.kind {
+            TraceKind::Line => self.draw_line(cx, chart, t, color, cancelled),
+            TraceKind::Scatter(style) => self.draw_scatter(cx, chart, t, style, color, cancelled),
+            TraceKind::Bars {
+                width,
+                baseline,
+                fill,
+            } => self.draw_bars(cx, chart, t, *width, *baseline, fill, color),
+            TraceKind::Area { baseline, fill } => {
+                self.draw_area(cx, chart, t, baseline, fill, color);
+                self.draw_line(cx, chart, t, color, cancelled);
+            }
+        }
+    }
+
+    /// Map a data point to pixel coordinates inside the chart rectangle
+    fn data_to_pixel(&self, chart: gtk::cairo::Rectangle, (x, y): (f64, f64)) -> (f64, f64) {
+        (
+            chart.x() + chart.width() * self.primary_x.data_to_axis(x),
+            chart.y() + chart.height() * (1.0 - self.primary_y.data_to_axis(y)),
+        )
+    }
+
+    /// y of a bar or area baseline. A log axis can't show zero or negative values, such
+    /// baselines are at the bottom of the chart.
+    fn baseline_y(&self, baseline: f64) -> f64 {
+        match self.primary_y.axis_type() {
+            AxisType::Log if baseline <= 0.0 => self.primary_y.range().0,
+            _ => baseline,
+        }
+    }
+
+    fn draw_line(
+        &self,
+        cx: &Context,
+        chart: gtk::cairo::Rectangle,
+        t: &Trace,
+        color: (f64, f64, f64),
+        cancelled: &dyn Fn() -> bool,
+    ) {
+        // non-finite values (NaN gaps, or non-positive values on a log axis) lift the pen.
+        // As a level of detail reduction that doesn't depend on x ordering, vertices
+        // within half a pixel of the last one drawn are skipped.
+        let mut pen_down = false;
+        let mut last = (f64::NAN, f64::NAN);
+        let mut skipped = None;
+        for (i, p) in t.values.iter().enumerate() {
+            if i % CANCEL_CHECK_POINTS == 0 && cancelled() {
+                cx.new_path();
+                return;
+            }
+            let (px_x, px_y) = self.data_to_pixel(chart, *p);
+            if !(px_x.is_finite() && px_y.is_finite()) {
+                if let Some((sx, sy)) = skipped.take() {
+                    cx.line_to(sx, sy);
+                }
+                pen_down = false;
+            } else if !pen_down {
+                cx.move_to(px_x, px_y);
+                pen_down = true;
+                last = (px_x, px_y);
+            } else if (px_x - last.0).abs() < 0.5 && (px_y - last.1).abs() < 0.5 {
+                skipped = Some((px_x, px_y));
+            } else {
+                cx.line_to(px_x, px_y);
+                last = (px_x, px_y);
+                skipped = None;
+            }
+        }
+        if let Some((sx, sy)) = skipped {
+            cx.line_to(sx, sy);
+        }
+
+        cx.set_line_width(2.0);
+        cx.set_source_rgb(color.0, color.1, color.2);
+        cx.stroke().unwrap();
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_bars(
+        &self,
+        cx: &Context,
+        chart: gtk::cairo::Rectangle,
+        t: &Trace,
+        width: f64,
+        baseline: f64,
+        fill: &FillStyle,
+        color: (f64, f64, f64),
+    ) {
+        let baseline = self.baseline_y(baseline);
+        for (x, y) in t.values.iter() {
+            let (left, top) = self.data_to_pixel(chart, (x - width / 2.0, *y));
+            let (right, bottom) = self.data_to_pixel(chart, (x + width / 2.0, baseline));
+            if [left, top, right, bottom].iter().all(|v| v.is_finite()) {
+                cx.rectangle(left, top, right - left, bottom - top);
+            }
+        }
+        fill_and_stroke(cx, fill, color);
+    }
+
+    fn draw_area(
+        &self,
+        cx: &Context,
+        chart: gtk::cairo::Rectangle,
+        t: &Trace,
+        baseline: &Baseline,
+        fill: &FillStyle,
+        color: (f64, f64, f64),
+    ) {
+        // non-finite points are left out, the polygon bridges over them
+        let upper: Vec<_> = t
+            .values
+            .iter()
+            .map(|p| self.data_to_pixel(chart, *p))
+            .filter(|(x, y)| x.is_finite() && y.is_finite())
+            .collect();
+        let (Some(first), Some(last)) = (upper.first(), upper.last()) else {
+            return;
+        };
+        let lower: Vec<_> = match baseline {
+            Baseline::Value(b) => {
+                let y_px = self
+                    .data_to_pixel(chart, (t.values[0].0, self.baseline_y(*b)))
+                    .1;
+                vec![(last.0, y_px), (first.0, y_px)]
+            }
+            Baseline::Trace(values) => values
+                .iter()
+                .rev()
+                .map(|p| self.data_to_pixel(chart, *p))
+                .filter(|(x, y)| x.is_finite() && y.is_finite())
+                .collect(),
+        };
+
+        cx.move_to(first.0, first.1);
+        for (x, y) in upper.iter().chain(lower.iter()) {
+            cx.line_to(*x, *y);
+        }
+        cx.close_path();
+
+        // the trace itself is drawn as a line on top, only the fill is needed here
+        let fill = FillStyle {
+            edge_width: 0.0,
+            ..fill.clone()
+        };
+        fill_and_stroke(cx, &fill, color);
+    }
+
+    fn draw_errors(
+        &self,
+        cx: &Context,
+        chart: gtk::cairo::Rectangle,
+        t: &Trace,
+        errors: &Errors,
+        color: (f64, f64, f64),
+    ) {
+        let finite = |(x, y): &(f64, f64)| x.is_finite() && y.is_finite();
+        match errors.style {
+            ErrorStyle::Band { alpha } => {
+                let Some(y_errors) = &errors.y else {
+                    return;
+                };
+                // upper edge left to right, lower edge back
+                let points = t.values.iter().enumerate();
+                let upper = points.clone().map(|(i, (x, y))| (*x, y + y_errors.at(i).1));
+                let lower = points.rev().map(|(i, (x, y))| (*x, y - y_errors.at(i).0));
+                let mut started = false;
+                for p in upper
+                    .chain(lower)
+                    .map(|p| self.data_to_pixel(chart, p))
+                    .filter(finite)
+                {
+                    if started {
+                        cx.line_to(p.0, p.1);
+                    } else {
+                        cx.move_to(p.0, p.1);
+                        started = true;
+                    }
+                }
+                cx.close_path();
+                cx.set_source_rgba(color.0, color.1, color.2, alpha);
+                cx.fill().unwrap();
+            }
+            ErrorStyle::Bars { cap } => {
+                for (i, (x, y)) in t.values.iter().enumerate() {
+                    let (x_err, y_err) = t.errors_at(i);
+                    if let Some((minus, plus)) = y_err {
+                        let low = self.data_to_pixel(chart, (*x, y - minus));
+                        let high = self.data_to_pixel(chart, (*x, y + plus));
+                        if finite(&low) && finite(&high) {
+                            cx.move_to(low.0, low.1);
+                            cx.line_to(high.0, high.1);
+                            for end in [low, high] {
+                                cx.move_to(end.0 - cap / 2.0, end.1);
+                                cx.line_to(end.0 + cap / 2.0, end.1);
+                            }
+                        }
+                    }
+                    if let Some((minus, plus)) = x_err {
+                        let left = self.data_to_pixel(chart, (x - minus, *y));
+                        let right = self.data_to_pixel(chart, (x + plus, *y));
+                        if finite(&left) && finite(&right) {
+                            cx.move_to(left.0, left.1);
+                            cx.line_to(right.0, right.1);
+                            for end in [left, right] {
+                                cx.move_to(end.0, end.1 - cap / 2.0);
+                                cx.line_to(end.0, end.1 + cap / 2.0);
+                            }
+                        }
+                    }
+                }
+                cx.set_source_rgb(color.0, color.1, color.2);
+                cx.set_line_width(1.0);
+                cx.stroke().unwrap();
+            }
+        }
+    }
+
+    /// Draw the mask, highlight violating trace segments and show a pass/fail summary
+    fn draw_mask(&self, cx: &Context, chart: gtk::cairo::Rectangle, mask: &Mask) {
+        let polyline = |points: &[(f64, f64)]| {
+            for (i, p) in points.iter().enumerate() {
+                let (x, y) = self.data_to_pixel(chart, *p);
+                if i == 0 {
+                    cx.move_to(x, y);
+                } else {
+                    cx.line_to(x, y);
+                }
+            }
+        };
+
+        let (r, g, b) = self.theme.mask;
+        cx.set_source_rgb(r, g, b);
+        cx.set_line_width(1.0);
+        match mask {
+            Mask::Limits { upper, lower } => {
+                cx.set_dash(&[6.0, 3.0], 0.0);
+                for limit in [upper, lower].into_iter().flatten() {
+                    polyline(limit);
+                }
+                cx.stroke().unwrap();
+                cx.set_dash(&[], 0.0);
+            }
+            Mask::Polygon(points) => {
+                polyline(points);
+                cx.close_path();
+                cx.set_source_rgba(r, g, b, 0.15);
+                cx.fill_preserve().unwrap();
+                cx.set_source_rgb(r, g, b);
+                cx.stroke().unwrap();
+            }
+        }
+
+        let results = self.mask_results();
+        for (t, result) in self.traces.iter().zip(&results) {
+            for (start, end) in &result.violations {
+                // include the segments leading into and out of the violation
+                let from = start.saturating_sub(1);
+                let to = (end + 1).min(t.values.len());
+                polyline(&t.points()[from..to]);
+            }
+        }
+        let (r, g, b) = self.theme.violation;
+        cx.set_source_rgb(r, g, b);
+        cx.set_line_width(3.0);
+        cx.stroke().unwrap();
+
+        let violations: usize = results.iter().map(|r| r.violations.len()).sum();
+        let first_x = results
+            .iter()
+            .filter_map(|r| r.first_x)
+            .min_by(f64::total_cmp);
+        let summary = match first_x {
+            None => "Mask: pass".to_owned(),
+            Some(x) => format!(
+                "Mask: FAIL, {violations} violation(s), first at x = {}",
+                format_number(x)
+            ),
+        };
+        let bottom = chart.y() + chart.height();
+        let pos = (chart.x(), bottom - self.fonts.annotation);
+        let font = self.fonts.font(self.fonts.annotation, true);
+        let (r, g, b) = self.theme.text;
+        cx.set_source_rgb(r, g, b);
+        text_aligned(cx, pos, &summary, TextPos::Right, &font, 5.0, false);
+    }
+
+    fn draw_legend(&self, cx: &Context, chart: gtk::cairo::Rectangle) {
+        if self.traces.is_empty() {
+            return;
+        }
+        let row_height = 1.5 * self.fonts.legend;
+        let font = self.fonts.font(self.fonts.legend, false);
+        let swatch = 20.0;
+        let box_width = 150.0;
+        let x = chart.x() + chart.width() - box_width - 10.0;
+        let y = chart.y() + 10.0;
+
+        cx.rectangle(x, y, box_width, row_height * self.traces.len() as f64 + 6.0);
+        let (r, g, b) = self.theme.chart;
+        cx.set_source_rgba(r, g, b, 0.85);
+        cx.fill_preserve().unwrap();
+        let (r, g, b) = self.theme.grid_major;
+        cx.set_source_rgb(r, g, b);
+        cx.set_line_width(1.0);
+        cx.stroke().unwrap();
+
+        for (i, t) in self.traces.iter().enumerate() {
+            let color = self.theme.trace_color(i);
+            let (sx, sy) = (x + 6.0, y + 3.0 + row_height * (i as f64 + 0.5));
+            match &t.kind {
+                TraceKind::Line => {
+                    cx.move_to(sx, sy);
+                    cx.line_to(sx + swatch, sy);
+                    cx.set_source_rgb(color.0, color.1, color.2);
+                    cx.set_line_width(2.0);
+                    cx.stroke().unwrap();
+                }
+                TraceKind::Scatter(style) => {
+                    cx.arc(
+                        sx + swatch / 2.0,
+                        sy,
+                        style.size.min(10.0) / 2.0,
+                        0.0,
+                        2.0 * PI,
+                    );
+                    cx.set_source_rgb(color.0, color.1, color.2);
+                    cx.fill().unwrap();
+                }
+                TraceKind::Bars { fill, .. } | TraceKind::Area { fill, .. } => {
+                    cx.rectangle(sx, sy - 5.0, swatch, 10.0);
+                    fill_and_stroke(cx, fill, color);
+                }
+            }
+            let (r, g, b) = self.theme.text;
+            cx.set_source_rgb(r, g, b);
+            text_aligned(
+                cx,
+                (sx + swatch, sy),
+                &t.name,
+                TextPos::Right,
+                &font,
+                5.0,
+                false,
+            );
+        }
+    }
+
+    fn draw_scatter(
+        &self,
+        cx: &Context,
+        chart: gtk::cairo::Rectangle,
+        t: &Trace,
+        style: &ScatterStyle,
+        color: (f64, f64, f64),
+        cancelled: &dyn Fn() -> bool,
+    ) {
+        let sizes = style.size_by.as_ref().and_then(|(name, (smin, smax))| {
+            let (vmin, vmax) = t.channel_range(name)?;
+            let span = if vmax > vmin { vmax - vmin } else { 1.0 };
+            Some(
+                t.channel(name)?
+                    .iter()
+                    .map(|v| smin + (v - vmin) / span * (smax - smin))
+                    .collect::<Vec<_>>(),
+            )
+        });
+        let colors = style.color_by.as_ref().and_then(|(name, colormap)| {
+            let values = t.channel(name)?;
+            Some(match &self.colorbar {
+                // keep colors consistent with the colorbar if it shows this channel
+                Some(cb) if cb.colormap == *colormap && cb.name == *name => {
+                    values.iter().map(|v| cb.color(*v)).collect()
+                }
+                _ => {
+                    let (vmin, vmax) = t.channel_range(name)?;
+                    let span = if vmax > vmin { vmax - vmin } else { 1.0 };
+                    values
+                        .iter()
+                        .map(|v| colormap.color((v - vmin) / span))
+                        .collect::<Vec<_>>()
+                }
+            })
+        });
+
+        cx.set_line_width(1.5);
+        for (i, p) in t.values.iter().enumerate() {
+            if i % CANCEL_CHECK_POINTS == 0 && cancelled() {
+                return;
+            }
+            let (px_x, px_y) = self.data_to_pixel(chart, *p);
+            let size = sizes.as_ref().map_or(style.size, |s| s[i]);
+            if !(px_x.is_finite() && px_y.is_finite() && size.is_finite()) {
+                continue;
+            }
+            let (r, g, b) = colors.as_ref().map_or(color, |c| c[i]);
+            cx.set_source_rgb(r, g, b);
+
+            let h = size / 2.0;
+            match style.marker {
+                Marker::Circle => {
+                    cx.arc(px_x, px_y, h, 0.0, 2.0 * PI);
+                    cx.fill().unwrap();
+                }
+                Marker::Square => {
+                    cx.rectangle(px_x - h, px_y - h, size, size);
+                    cx.fill().unwrap();
+                }
+                Marker::Diamond => {
+                    cx.move_to(px_x, px_y - h);
+                    cx.line_to(px_x + h, px_y);
+                    cx.line_to(px_x, px_y + h);
+                    cx.line_to(px_x - h, px_y);
+                    cx.close_path();
+                    cx.fill().unwrap();
+                }
+                Marker::Cross => {
+                    cx.move_to(px_x - h, px_y - h);
+                    cx.line_to(px_x + h, px_y + h);
+                    cx.move_to(px_x - h, px_y + h);
+                    cx.line_to(px_x + h, px_y - h);
+                    cx.stroke().unwrap();
+                }
+            }
+        }
     }
 }
 /*
@@ -456,7 +1205,8 @@ pub mod demo {
     fn export_svg(axes: &mut Axes) {
         let svg = gtk::cairo::SvgSurface::new(800.0, 500.0, Some("abc.svg")).unwrap();
         let mut cx = gtk::cairo::Context::new(svg).unwrap();
-        cx.set_source_rgb(1.0, 1.0, 1.0);
+        let (r, g, b) = axes.theme.background;
+        cx.set_source_rgb(r, g, b);
         cx.paint().unwrap();
         axes.draw(&mut cx, Rectangle::new(0.0, 0.0, 800.0, 500.0));
     }
@@ -478,7 +1228,7 @@ pub mod demo {
         }
 
         let state = Rc::new(RefCell::new(SharedState {
-            axes: Axes::linear(None),
+            axes: Axes::linear(),
             current_rect: Rectangle::new(0.0, 0.0, 1.0, 1.0),
             cursor: AxesCursorPosition::None,
         }));
@@ -486,7 +1236,8 @@ pub mod demo {
         let st = state.clone();
         let position = gtk::cairo::Rectangle::new(0.0, 0.0, 1.0, 1.0);
         darea.borrow().set_draw_func(move |_da, cx, width, height| {
-            cx.set_source_rgb(1.0, 1.0, 1.0);
+            let (r, g, b) = st.borrow().axes.theme.background;
+            cx.set_source_rgb(r, g, b);
             cx.paint().unwrap();
 
             st.borrow_mut().current_rect = Rectangle::new(
@@ -569,3 +1320,50 @@ pub mod demo {
     }
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::trace::tests::random_trace;
+
+    #[test]
+    fn draw_random_traces() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let surface =
+            gtk::cairo::ImageSurface::create(gtk::cairo::Format::ARgb32, 800, 600).unwrap();
+        let cx = Context::new(&surface).unwrap();
+        for i in 0..100 {
+            let mut axes = match i % 3 {
+                0 => Axes::linear(),
+                1 => Axes::semilogx(),
+                _ => Axes::semilogy(),
+            };
+            axes.legend = rng.gen_bool(0.5);
+            for _ in 0..rng.gen_range(0..4) {
+                axes.add_trace(random_trace(&mut rng));
+            }
+            axes.zoom_fit();
+            let (width, height) = (rng.gen_range(200.0..800.0), rng.gen_range(150.0..600.0));
+            let rect = gtk::cairo::Rectangle::new(0.0, 0.0, width, height);
+            let (x, y) = (rng.gen_range(0.0..width), rng.gen_range(0.0..height));
+            axes.cursor = axes.snap_cursor(axes.cursor_position(rect, x, y));
+            axes.draw(&cx, rect);
+            assert_eq!(cx.status(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn markers_survive_annotation_changes() {
+        let mut axes = Axes::linear();
+        axes.add_annotation(Annotation::new(Shape::HLine(0.5)));
+        axes.set_markers(Some((0.2, -0.4)));
+        axes.annotations.remove(0);
+        axes.add_annotation(Annotation::new(Shape::VLine(0.9)));
+        assert_eq!(axes.marker_range(), Some((-0.4, 0.2)));
+        axes.set_markers(None);
+        assert_eq!(axes.marker_range(), None);
+        assert_eq!(axes.annotations.len(), 1);
+    }
+}
@@ -1,5 +1,8 @@
-use crate::cairo_utils::{text_aligned, PixelContext, TextPos};
+use pangocairo::pango;
+
+use crate::cairo_utils::{text_aligned, Fonts, PixelContext, TextPos};
 use crate::locator::{LinLocator, Locator, LogLocator};
+use crate::theme::{Color, Theme};
 
 #[derive(Clone, Copy)]
 pub enum AxisPlacement {
@@ -9,18 +12,64 @@ pub enum AxisPlacement {
     Top,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum AxisType {
     Lin,
     Log,
 }
 
+impl AxisType {
+    /// Position on the axis scale, where equal distances are equal on screen (decades on
+    /// a log axis). Not finite for values a log axis can't show.
+    pub fn data_to_scale(self, v: f64) -> f64 {
+        match self {
+            AxisType::Lin => v,
+            AxisType::Log => v.log10(),
+        }
+    }
+
+    pub fn scale_to_data(self, v: f64) -> f64 {
+        match self {
+            AxisType::Lin => v,
+            AxisType::Log => 10.0_f64.powf(v),
+        }
+    }
+}
+
+/// How the range of an Axis follows the data and user interaction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScaleMode {
+    /// range only changes through zoom/pan/fit
+    Manual,
+    /// fit all data every time the data changes
+    Auto,
+    /// fit the data visible in the current x range (only meaningful for y axes)
+    AutoVisible,
+    /// range is fixed, zoom/pan/fit are ignored
+    Locked,
+    /// keep the newest `width` data units visible, scrolling as data arrives (for live
+    /// data on a linear x axis). Zooming or panning switches to `Manual`.
+    Follow(f64),
+}
+
+/// Tick positions (data values) of an Axis
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Ticks {
+    pub major: Vec<f64>,
+    pub minor: Vec<f64>,
+}
+
 pub struct Axis {
     placement: AxisPlacement,
     axis_type: AxisType,
-    pub range: (f64, f64),
+    range: (f64, f64),
     label: Option<String>,
     pub locator: Box<dyn Locator>,
+    pub scale_mode: ScaleMode,
+    /// optional lower and upper bounds the range can never exceed
+    pub limits: (Option<f64>, Option<f64>),
+    /// extra space added on both sides when fitting, as a fraction of the data span
+    pub padding: f64,
 }
 
 impl Axis {
@@ -50,55 +99,185 @@ impl Axis {
                 AxisType::Lin => Box::new(LinLocator::default()),
                 AxisType::Log => Box::new(LogLocator::default()),
             },
+            scale_mode: ScaleMode::Manual,
+            limits: (None, None),
+            padding: 0.0,
+        }
+    }
+
+    /// Ticks of the axis drawn `length` pixels long, as `draw` places them
+    pub fn ticks(&self, length: f64) -> Ticks {
+        self.locate_ticks(length).0
+    }
+
+    /// Ticks and the decimals of their labels (linear axes)
+    fn locate_ticks(&self, length: f64) -> (Ticks, usize) {
+        let (major, minor, decimals) = self.locator.get_ticks(self.range, Some(50.0 / length));
+        (Ticks { major, minor }, decimals)
+    }
+
+    pub fn axis_type(&self) -> AxisType {
+        self.axis_type
+    }
+
+    pub fn is_linear(&self) -> bool {
+        matches!(self.axis_type, AxisType::Lin)
+    }
+
+    pub fn set_label(&mut self, label: Option<&str>) {
+        self.label = label.map(str::to_owned);
+    }
+
+    pub fn range(&self) -> (f64, f64) {
+        self.range
+    }
+
+    /// Set the range directly (works in every ScaleMode), clamped to the limits
+    pub fn set_range(&mut self, range: (f64, f64)) {
+        let (mut start, mut end) = range;
+        if let Some(lo) = self.limits.0 {
+            start = start.max(lo);
+        }
+        if let Some(hi) = self.limits.1 {
+            end = end.min(hi);
+        }
+        if start < end {
+            self.range = (start, end);
+        }
+    }
+
+    /// Set the range to show data between `min` and `max`, including padding.
+    /// Ignored for locked axes.
+    pub fn fit(&mut self, min: f64, max: f64) {
+        if self.scale_mode == ScaleMode::Locked || min.is_nan() || max.is_nan() || min > max {
+            return;
+        }
+        match self.axis_type {
+            AxisType::Lin => {
+                let span = if max > min {
+                    max - min
+                } else {
+                    min.abs().max(1.0)
+                };
+                let pad = if max > min {
+                    self.padding * span
+                } else {
+                    0.5 * span
+                };
+                self.set_range((min - pad, max + pad));
+            }
+            AxisType::Log => {
+                if max <= 0.0 {
+                    return;
+                }
+                // non-positive values can't be shown, keep a few decades below the maximum
+                let min = if min > 0.0 { min } else { max * 1e-3 };
+                let decades = (max / min).log10();
+                let pad = if decades > 0.0 {
+                    self.padding * decades
+                } else {
+                    0.5
+                };
+                self.set_range((min / 10.0_f64.powf(pad), max * 10.0_f64.powf(pad)));
+            }
+        }
+    }
+
+    /// Scroll so `latest` is at the end of the range, in `Follow` mode
+    pub fn follow(&mut self, latest: f64) {
+        if let ScaleMode::Follow(width) = self.scale_mode {
+            self.set_range((latest - width, latest));
         }
     }
 
     pub fn zoom_at(&mut self, x_01: f64, scale: f64) {
+        match self.scale_mode {
+            ScaleMode::Locked => return,
+            ScaleMode::Follow(_) => self.scale_mode = ScaleMode::Manual,
+            _ => {}
+        }
         let new_width = scale * (self.range.1 - self.range.0);
         let x_data = self.axis_to_data(x_01);
 
         match self.axis_type {
             AxisType::Lin => {
-                self.range.0 = x_data - x_01 * new_width;
-                self.range.1 = x_data + (1.0 - x_01) * new_width;
+                self.set_range((x_data - x_01 * new_width, x_data + (1.0 - x_01) * new_width));
             }
             AxisType::Log => {
                 let left = self.axis_to_data((1.0 - scale) * x_01);
                 let right = self.axis_to_data(1.0 + (scale - 1.0) * (1.0 - x_01));
 
-                self.range.0 = left;
-                self.range.1 = right;
+                self.set_range((left, right));
             }
         }
     }
 
-    pub fn draw(&self, cx: &gtk::cairo::Context, start_pos: (f64, f64), length: f64) {
-        PixelContext::new(cx).move_to(start_pos.0, start_pos.1);
-        cx.set_line_width(1.0);
+    /// Shift the range by `delta_01` (relative to the axis length).
+    /// The width of the range is preserved when running into a limit.
+    pub fn pan(&mut self, delta_01: f64) {
+        match self.scale_mode {
+            ScaleMode::Locked => return,
+            ScaleMode::Follow(_) => self.scale_mode = ScaleMode::Manual,
+            _ => {}
+        }
+        let (mut start, mut end) = (
+            self.axis_to_data(delta_01),
+            self.axis_to_data(1.0 + delta_01),
+        );
 
-        let (ticks_major, ticks_minor, decimals);
-        match self.placement {
-            AxisPlacement::Left | AxisPlacement::Right => {
-                (ticks_major, ticks_minor, decimals) =
-                    self.locator.get_ticks(self.range, Some(50.0 / length));
+        match self.axis_type {
+            AxisType::Lin => {
+                if let Some(lo) = self.limits.0.filter(|lo| start < *lo) {
+                    (start, end) = (lo, end + lo - start);
+                }
+                if let Some(hi) = self.limits.1.filter(|hi| end > *hi) {
+                    (start, end) = (start - (end - hi), hi);
+                }
             }
-            _ => {
-                (ticks_major, ticks_minor, decimals) =
-                    self.locator.get_ticks(self.range, Some(50.0 / length));
+            AxisType::Log => {
+                if let Some(lo) = self.limits.0.filter(|lo| start < *lo) {
+                    (start, end) = (lo, end * lo / start);
+                }
+                if let Some(hi) = self.limits.1.filter(|hi| end > *hi) {
+                    (start, end) = (start * hi / end, hi);
+                }
             }
         }
+        self.set_range((start, end));
+    }
+
+    /// Draw the axis line, ticks and label, returns the ticks (e.g. for the grid)
+    pub fn draw(
+        &self,
+        cx: &gtk::cairo::Context,
+        start_pos: (f64, f64),
+        length: f64,
+        fonts: &Fonts,
+        theme: &Theme,
+    ) -> Ticks {
+        PixelContext::new(cx).move_to(start_pos.0, start_pos.1);
+        cx.set_line_width(1.0);
+        let (r, g, b) = theme.axis;
+        cx.set_source_rgb(r, g, b);
+
+        let (ticks, decimals) = self.locate_ticks(length);
 
         PixelContext::new(cx).move_to(start_pos.0, start_pos.1);
         let prec = match self.axis_type {
             AxisType::Lin => Some(decimals),
             AxisType::Log => None,
         };
-        self.draw_ticks(cx, length, ticks_major, 8.0, true, prec);
+        let tick_font = fonts.font(fonts.tick, false);
+        let labels = (&tick_font, theme.text);
+        self.draw_ticks(cx, length, &ticks.major, 8.0, Some(labels), prec);
 
         PixelContext::new(cx).move_to(start_pos.0, start_pos.1);
-        self.draw_ticks(cx, length, ticks_minor, 3.0, false, None);
+        self.draw_ticks(cx, length, &ticks.minor, 3.0, None, None);
 
         if let Some(text) = &self.label {
+            let font = fonts.font(fonts.label, true);
+            let (r, g, b) = theme.text;
+            cx.set_source_rgb(r, g, b);
             match self.placement {
                 AxisPlacement::Left => {
                     text_aligned(
@@ -106,44 +285,66 @@ impl Axis {
                         (start_pos.0, start_pos.1 - length / 2.0),
                         &text,
                         TextPos::Left,
-                        15.0,
+                        &font,
                         50.0,
                         true,
+                    );
+                }
+                AxisPlacement::Right => {
+                    text_aligned(
+                        cx,
+                        (start_pos.0, start_pos.1 - length / 2.0),
+                        &text,
+                        TextPos::Right,
+                        &font,
+                        50.0,
                         true,
                     );
                 }
-                AxisPlacement::Right => {}
                 AxisPlacement::Bottom => {
                     text_aligned(
                         cx,
                         (start_pos.0 + length / 2.0, start_pos.1),
                         &text,
                         TextPos::Bottom,
-                        15.0,
+                        &font,
+                        30.0,
+                        false,
+                    );
+                }
+                AxisPlacement::Top => {
+                    text_aligned(
+                        cx,
+                        (start_pos.0 + length / 2.0, start_pos.1),
+                        &text,
+                        TextPos::Top,
+                        &font,
                         30.0,
                         false,
-                        true,
                     );
                 }
-                AxisPlacement::Top => {}
             }
         }
         PixelContext::new(cx).move_to(start_pos.0, start_pos.1);
+        ticks
     }
 
     pub fn draw_ticks(
         &self,
         cx: &gtk::cairo::Context,
         length: f64,
-        ticks: Vec<f64>,
+        ticks: &[f64],
         tick_size: f64,
-        with_labels: bool,
+        // font and color of the tick labels, no labels if None
+        labels: Option<(&pango::FontDescription, Color)>,
         decimals: Option<usize>,
     ) {
         // save start position
         let start_point = cx.current_point().unwrap();
+        // the labels change the source, the ticks are stroked at the end
+        let line_source = cx.source();
 
-        for t in ticks {
+        for &t in ticks {
             let t_01 = self.data_to_axis(t);
 
             let text = match decimals {
@@ -169,69 +370,70 @@ impl Axis {
                 AxisPlacement::Left => {
                     PixelContext::new(cx).rel_move_to(0.0, -t_01 * length);
                     PixelContext::new(cx).rel_line_to(-tick_size, 0.0);
-                    if with_labels {
+                    if let Some((font, (r, g, b))) = labels {
+                        cx.set_source_rgb(r, g, b);
                         text_aligned(
                             cx,
                             (start_point.0 - tick_size, start_point.1 - t_01 * length),
                             &text,
                             TextPos::Left,
-                            12.0,
+                            font,
                             5.0,
                             false,
-                            false,
                         );
                     }
                 }
                 AxisPlacement::Right => {
                     PixelContext::new(cx).rel_move_to(0.0, -t_01 * length);
                     PixelContext::new(cx).rel_line_to(tick_size, 0.0);
-                    if with_labels {
+                    if let Some((font, (r, g, b))) = labels {
+                        cx.set_source_rgb(r, g, b);
                         text_aligned(
                             cx,
                             (start_point.0 + tick_size, start_point.1 - t_01 * length),
                             &text,
                             TextPos::Right,
-                            12.0,
+                            font,
                             5.0,
                             false,
-                            false,
                         );
                     }
                 }
                 AxisPlacement::Top => {
                     PixelContext::new(cx).rel_move_to(t_01 * length, 0.0);
                     PixelContext::new(cx).rel_line_to(0.0, -tick_size);
-                    if with_labels {
+                    if let Some((font, (r, g, b))) = labels {
+                        cx.set_source_rgb(r, g, b);
                         text_aligned(
                             cx,
                             (start_point.0 + t_01 * length, start_point.1 - tick_size),
                             &text,
                             TextPos::Top,
-                            12.0,
+                            font,
                             5.0,
                             false,
-                            false,
                         );
                     }
                 }
                 AxisPlacement::Bottom => {
                     PixelContext::new(cx).rel_move_to(t_01 * length, 0.0);
                     PixelContext::new(cx).rel_line_to(0.0, tick_size);
-                    if with_labels {
+                    if let Some((font, (r, g, b))) = labels {
+                        cx.set_source_rgb(r, g, b);
                         text_aligned(
                             cx,
                             (start_point.0 + t_01 * length, start_point.1 + tick_size),
                             &text,
                             TextPos::Bottom,
-                            12.0,
+                            font,
                             5.0,
                             false,
-                            false,
                         );
                     }
                 }
             }
         }
+        cx.set_source(&line_source).unwrap();
         cx.stroke().unwrap();
     }
 }
@@ -1,6 +1,11 @@
 use core::f64;
 use std::f64::consts::PI;
 
+use pangocairo::pango;
+use pangocairo::pango::prelude::*;
+
+use crate::markup::to_pango;
+
 #[derive(Debug)]
 pub enum TextPos {
     Left,
@@ -9,28 +14,71 @@ pub enum TextPos {
     Bottom,
 }
 
-pub fn text_aligned(
-    cx: &gtk::cairo::Context,
-    position: (f64, f64),
+/// Font family and sizes (in pixels) of the text elements of an Axes or Plot
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fonts {
+    /// Pango family name, or a comma separated list of fallbacks
+    pub family: String,
+    /// tick labels
+    pub tick: f64,
+    /// axis labels
+    pub label: f64,
+    /// Axes titles
+    pub title: f64,
+    /// figure title of a Plot
+    pub plot_title: f64,
+    pub legend: f64,
+    /// annotations, cursor readouts and measurement summaries
+    pub annotation: f64,
+}
+
+impl Default for Fonts {
+    fn default() -> Self {
+        Self {
+            family: "Sans".to_owned(),
+            tick: 12.0,
+            label: 15.0,
+            title: 13.0,
+            plot_title: 16.0,
+            legend: 12.0,
+            annotation: 12.0,
+        }
+    }
+}
+
+impl Fonts {
+    /// Font of the family at `size` pixels (user space units, so it scales with the
+    /// device scale of the target like everything else)
+    pub fn font(&self, size: f64, bold: bool) -> pango::FontDescription {
+        let mut font = pango::FontDescription::new();
+        font.set_family(&self.family);
+        font.set_absolute_size(size * pango::SCALE as f64);
+        if bold {
+            font.set_weight(pango::Weight::Bold);
+        }
+        font
+    }
+}
+
+/// Layout of `text` in `font`, and the offset from the anchor position of the point
+/// it is shown at (before rotating for vertical text), as `text_aligned` places it
+fn aligned_layout(
+    context: &pango::Context,
     text: &str,
     mut placement: TextPos,
-    fontsize: f64,
+    font: &pango::FontDescription,
     spacing: f64,
     vertical: bool,
-    bold: bool,
-) {
-    cx.set_font_size(fontsize);
-    cx.set_source_rgb(0.0, 0.0, 0.0);
-    cx.select_font_face(
-        "Roboto Light",
-        gtk::cairo::FontSlant::Normal,
-        if bold {
-            gtk::cairo::FontWeight::Bold
-        } else {
-            gtk::cairo::FontWeight::Normal
-        },
-    );
-    let te = cx.text_extents(text).unwrap();
+) -> (pango::Layout, (f64, f64)) {
+    let layout = pango::Layout::new(context);
+    layout.set_font_description(Some(font));
+    layout.set_markup(&to_pango(text));
+    let units = |v: i32| v as f64 / pango::SCALE as f64;
+    let width = units(layout.size().0);
+    let metrics = context.metrics(Some(font), None);
+    let (ascent, descent) = (units(metrics.ascent()), units(metrics.descent()));
+    // from the top of the layout
+    let baseline = units(layout.baseline());
 
     // we really need the placement "from the text's perspective" (rotated)
     if vertical {
@@ -42,31 +90,85 @@ pub fn text_aligned(
         }
     }
 
-    // Find relative position of the text reference point to achieve 'placement'
-    // note: text y position needs to be integer to avoid 'blurring'
+    // Find the position of the top left corner of the layout to achieve 'placement'
     let (dx, dy) = match placement {
-        TextPos::Left => (-te.x_advance() - spacing, te.height() / 2.0),
-        TextPos::Right => (spacing, te.height() / 2.0),
-        TextPos::Top => (-te.x_advance() / 2.0, -spacing - 0.5),
-        TextPos::Bottom => (-te.x_advance() / 2.0, te.height() + spacing + 0.5),
+        TextPos::Left => (-width - spacing, (ascent - descent) / 2.0 - baseline),
+        TextPos::Right => (spacing, (ascent - descent) / 2.0 - baseline),
+        TextPos::Top => (-width / 2.0, -spacing - descent - baseline),
+        TextPos::Bottom => (-width / 2.0, spacing + ascent - baseline),
+    };
+    let origin = match vertical {
+        false => (dx, dy),
+        true => (dy, -dx),
     };
+    (layout, origin)
+}
 
-    match vertical {
-        false => {
-            cx.translate((position.0 + dx).round(), (position.1 + dy).round());
-        }
-        true => {
-            cx.identity_matrix();
-            // cx.translate(-position.1 + dx, position.0 + dy);
-            // cx.translate(20.0, 100.0);
-            cx.translate((position.0 + dy).round(), (position.1 - dx).round());
-            cx.rotate(-PI / 2.0);
-        }
+/// Draw text next to `position` (on the side given by `placement`), with the label
+/// markup of `markup::to_pango` (sub/superscripts, Greek letters, bold/italic), in the
+/// current source color.
+/// Vertical alignment uses the ascent and descent of the font rather than the extents
+/// of the text, so labels line up whatever characters they contain. The current path
+/// of `cx` is kept.
+pub fn text_aligned(
+    cx: &gtk::cairo::Context,
+    position: (f64, f64),
+    text: &str,
+    placement: TextPos,
+    font: &pango::FontDescription,
+    spacing: f64,
+    vertical: bool,
+) {
+    let context = pangocairo::functions::create_context(cx);
+    let (layout, (dx, dy)) = aligned_layout(&context, text, placement, font, spacing, vertical);
+
+    // the layout is shown at the current point, which is part of the path
+    let path = cx.copy_path().ok();
+    cx.new_path();
+    cx.save().unwrap();
+    // note: text position needs to be integer to avoid 'blurring'
+    cx.translate((position.0 + dx).round(), (position.1 + dy).round());
+    if vertical {
+        cx.rotate(-PI / 2.0);
     }
     cx.move_to(0.0, 0.0);
-    cx.show_text(text).unwrap();
-    cx.stroke().unwrap();
-    cx.identity_matrix();
+    pangocairo::functions::show_layout(cx, &layout);
+    cx.restore().unwrap();
+    cx.new_path();
+    if let Some(path) = path {
+        cx.append_path(&path);
+    }
+}
+
+/// Pixel rectangle `text_aligned` covers with the same arguments, from the logical
+/// extents of the layout
+pub fn text_rect(
+    position: (f64, f64),
+    text: &str,
+    placement: TextPos,
+    font: &pango::FontDescription,
+    spacing: f64,
+    vertical: bool,
+) -> gtk::cairo::Rectangle {
+    let context = pangocairo::FontMap::default().create_context();
+    let (layout, (dx, dy)) = aligned_layout(&context, text, placement, font, spacing, vertical);
+    let units = |v: i32| v as f64 / pango::SCALE as f64;
+    let (width, height) = (units(layout.size().0), units(layout.size().1));
+    let (x, y) = ((position.0 + dx).round(), (position.1 + dy).round());
+    match vertical {
+        false => gtk::cairo::Rectangle::new(x, y, width, height),
+        // rotated by -90 degrees around (x, y)
+        true => gtk::cairo::Rectangle::new(x, y - width, height, width),
+    }
+}
+
+/// Compact number formatting for cursor readouts
+pub fn format_number(v: f64) -> String {
+    if v != 0.0 && (v.abs() >= 1e4 || v.abs() < 1e-3) {
+        format!("{v:.3e}")
+    } else {
+        format!("{v:.4}")
+    }
 }
 
 pub struct PixelContext<'a> {
@@ -79,13 +181,15 @@ impl<'a> PixelContext<'a> {
     }
 
     pub fn move_to(&self, x: f64, y: f64) {
-        self.cx.move_to((x - 0.5).round() + 0.5, (y - 0.5).round() + 0.5)
+        self.cx
+            .move_to((x - 0.5).round() + 0.5, (y - 0.5).round() + 0.5)
     }
     pub fn rel_move_to(&self, x: f64, y: f64) {
         self.cx.rel_move_to(x.round(), y.round())
     }
     pub fn line_to(&self, x: f64, y: f64) {
-        self.cx.line_to((x - 0.5).round() + 0.5, (y - 0.5).round() + 0.5)
+        self.cx
+            .line_to((x - 0.5).round() + 0.5, (y - 0.5).round() + 0.5)
     }
     pub fn rel_line_to(&self, x: f64, y: f64) {
         self.cx.rel_line_to(x.round(), y.round())
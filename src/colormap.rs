@@ -0,0 +1,127 @@
+use crate::axis::{Axis, AxisPlacement, AxisType, ScaleMode};
+use crate::cairo_utils::{Fonts, PixelContext};
+use crate::theme::Theme;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Colormap {
+    Viridis,
+    Magma,
+    Grayscale,
+    /// blue - light gray - red, for values around a center
+    Diverging,
+}
+
+// equally spaced color stops (0xRRGGBB)
+const VIRIDIS: [u32; 11] = [
+    0x440154, 0x482475, 0x414487, 0x355f8d, 0x2a788e, 0x21918c, 0x22a884, 0x44bf70, 0x7ad151,
+    0xbddf26, 0xfde725,
+];
+const MAGMA: [u32; 11] = [
+    0x000004, 0x140e36, 0x3b0f70, 0x641a80, 0x8c2981, 0xb73779, 0xde4968, 0xf7705c, 0xfe9f6d,
+    0xfecf92, 0xfcfdbf,
+];
+const GRAYSCALE: [u32; 2] = [0x000000, 0xffffff];
+const DIVERGING: [u32; 5] = [0x3b4cc0, 0x7b9ff9, 0xdddddd, 0xf49a7b, 0xb40426];
+
+impl Colormap {
+    fn stops(&self) -> &'static [u32] {
+        match self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Magma => &MAGMA,
+            Colormap::Grayscale => &GRAYSCALE,
+            Colormap::Diverging => &DIVERGING,
+        }
+    }
+
+    /// RGB color for a value between 0 and 1 (clamped, NaN maps to 0)
+    pub fn color(&self, v_01: f64) -> (f64, f64, f64) {
+        let stops = self.stops();
+        let v = if v_01.is_nan() {
+            0.0
+        } else {
+            v_01.clamp(0.0, 1.0)
+        };
+        let pos = v * (stops.len() - 1) as f64;
+        let i = (pos.floor() as usize).min(stops.len() - 2);
+        let frac = pos - i as f64;
+
+        let channel = |c: u32, shift: u32| ((c >> shift) & 0xff) as f64 / 255.0;
+        let lerp = |shift| {
+            let a = channel(stops[i], shift);
+            let b = channel(stops[i + 1], shift);
+            a + frac * (b - a)
+        };
+        (lerp(16), lerp(8), lerp(0))
+    }
+}
+
+/// Color scale drawn in the right margin of an Axes
+pub struct Colorbar {
+    /// channel or image whose values are shown, also the initial axis label
+    pub name: String,
+    pub colormap: Colormap,
+    /// maps data values to the colorbar, its range is the range of the colormap
+    pub axis: Axis,
+    /// width of the color strip in pixels
+    pub width: f64,
+}
+
+impl Colorbar {
+    pub fn new(colormap: Colormap, range: (f64, f64), label: &str) -> Self {
+        let mut axis = Axis::new(AxisPlacement::Right, AxisType::Lin, range);
+        axis.set_label(Some(label));
+        // the colorbar range only changes with the data
+        axis.scale_mode = ScaleMode::Locked;
+        Self {
+            name: label.to_owned(),
+            colormap,
+            axis,
+            width: 15.0,
+        }
+    }
+
+    /// Horizontal space needed next to the chart, including tick labels
+    pub fn margin(&self) -> f64 {
+        self.width + 75.0
+    }
+
+    /// Draw the color strip along the left edge of `rect` (pixel coordinates), with ticks
+    pub fn draw(
+        &self,
+        cx: &gtk::cairo::Context,
+        rect: gtk::cairo::Rectangle,
+        fonts: &Fonts,
+        theme: &Theme,
+    ) {
+        let gradient =
+            gtk::cairo::LinearGradient::new(0.0, rect.y() + rect.height(), 0.0, rect.y());
+        let n = 32;
+        for k in 0..=n {
+            let pos = k as f64 / n as f64;
+            let (r, g, b) = self.colormap.color(pos);
+            gradient.add_color_stop_rgb(pos, r, g, b);
+        }
+        cx.rectangle(rect.x(), rect.y(), self.width, rect.height());
+        cx.set_source(&gradient).unwrap();
+        cx.fill().unwrap();
+
+        cx.set_line_width(1.0);
+        let (r, g, b) = theme.axis;
+        cx.set_source_rgb(r, g, b);
+        PixelContext::new(cx).rectangle(rect.x(), rect.y(), self.width, rect.height());
+        cx.stroke().unwrap();
+
+        self.axis.draw(
+            cx,
+            (rect.x() + self.width, rect.y() + rect.height()),
+            rect.height(),
+            fonts,
+            theme,
+        );
+    }
+
+    /// Color for a data value
+    pub fn color(&self, v: f64) -> (f64, f64, f64) {
+        self.colormap.color(self.axis.data_to_axis(v))
+    }
+}
@@ -0,0 +1,680 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::trace::Trace;
+
+/// Error from parsing or evaluating an expression
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExprError {
+    pub message: String,
+    /// byte offset in the expression text, for parse errors
+    pub position: Option<usize>,
+}
+
+impl ExprError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            position: None,
+        }
+    }
+
+    fn at(message: impl Into<String>, position: usize) -> Self {
+        Self {
+            message: message.into(),
+            position: Some(position),
+        }
+    }
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            Some(p) => write!(f, "{} (at {})", self.message, p),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+/// Parsed expression over traces
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    /// reference to a trace by name, e.g. `out`, `V(out)` or `I(R1)`
+    Signal(String),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+}
+
+/// SPICE style scale suffixes (case insensitive), `meg` must be checked before `m`
+const SUFFIXES: [(&str, f64); 9] = [
+    ("meg", 1e6),
+    ("f", 1e-15),
+    ("p", 1e-12),
+    ("n", 1e-9),
+    ("u", 1e-6),
+    ("m", 1e-3),
+    ("k", 1e3),
+    ("g", 1e9),
+    ("t", 1e12),
+];
+
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, ExprError> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let (pos, c) = chars[i];
+        let token = match c {
+            ' ' | '\t' => {
+                i += 1;
+                continue;
+            }
+            '+' | '-' | '*' | '/' | '^' => Token::Op(c),
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            '0'..='9' | '.' => {
+                while i < chars.len() && (chars[i].1.is_ascii_digit() || chars[i].1 == '.') {
+                    i += 1;
+                }
+                // exponent, only if followed by digits (so `1e` isn't swallowed)
+                if i + 1 < chars.len() && matches!(chars[i].1, 'e' | 'E') {
+                    let sign = matches!(chars[i + 1].1, '+' | '-') as usize;
+                    if chars
+                        .get(i + 1 + sign)
+                        .is_some_and(|(_, c)| c.is_ascii_digit())
+                    {
+                        i += 1 + sign;
+                        while i < chars.len() && chars[i].1.is_ascii_digit() {
+                            i += 1;
+                        }
+                    }
+                }
+                let end = chars.get(i).map_or(text.len(), |(p, _)| *p);
+                let mut value: f64 = text[pos..end]
+                    .parse()
+                    .map_err(|_| ExprError::at("invalid number", pos))?;
+
+                // scale suffix, the rest of the letters (e.g. the unit in `10ns`) is ignored
+                let letters_end = chars[i..]
+                    .iter()
+                    .position(|(_, c)| !c.is_ascii_alphabetic())
+                    .map_or(chars.len(), |n| i + n);
+                let letters = text[end..chars.get(letters_end).map_or(text.len(), |(p, _)| *p)]
+                    .to_ascii_lowercase();
+                if let Some((_, scale)) = SUFFIXES.iter().find(|(s, _)| letters.starts_with(s)) {
+                    value *= scale;
+                }
+                i = letters_end;
+                tokens.push((Token::Number(value), pos));
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = pos;
+                while i < chars.len() && (chars[i].1.is_alphanumeric() || chars[i].1 == '_') {
+                    i += 1;
+                }
+                let end = chars.get(i).map_or(text.len(), |(p, _)| *p);
+                tokens.push((Token::Ident(text[start..end].to_owned()), start));
+                continue;
+            }
+            _ => return Err(ExprError::at(format!("unexpected character '{c}'"), pos)),
+        };
+        tokens.push((token, pos));
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser. Precedence: `+ -` < `* /` < unary `-` < `^` (right associative)
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |(_, p)| *p)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(t, _)| t.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, token: Token, what: &str) -> Result<(), ExprError> {
+        if self.peek() == Some(&token) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(ExprError::at(format!("expected {what}"), self.offset()))
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, ExprError> {
+        let mut lhs = self.product()?;
+        while let Some(Token::Op(c @ ('+' | '-'))) = self.peek() {
+            let op = if *c == '+' {
+                BinaryOp::Add
+            } else {
+                BinaryOp::Sub
+            };
+            self.pos += 1;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.product()?));
+        }
+        Ok(lhs)
+    }
+
+    fn product(&mut self) -> Result<Expr, ExprError> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Op(c @ ('*' | '/'))) = self.peek() {
+            let op = if *c == '*' {
+                BinaryOp::Mul
+            } else {
+                BinaryOp::Div
+            };
+            self.pos += 1;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ExprError> {
+        match self.peek() {
+            Some(Token::Op('-')) => {
+                self.pos += 1;
+                Ok(Expr::Neg(Box::new(self.unary()?)))
+            }
+            Some(Token::Op('+')) => {
+                self.pos += 1;
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> Result<Expr, ExprError> {
+        let base = self.atom()?;
+        if let Some(Token::Op('^')) = self.peek() {
+            self.pos += 1;
+            let exponent = self.unary()?;
+            return Ok(Expr::Binary(
+                BinaryOp::Pow,
+                Box::new(base),
+                Box::new(exponent),
+            ));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Expr, ExprError> {
+        let offset = self.offset();
+        match self.next() {
+            Some(Token::Number(v)) => Ok(Expr::Number(v)),
+            Some(Token::LParen) => {
+                let e = self.sum()?;
+                self.expect(Token::RParen, "')'")?;
+                Ok(e)
+            }
+            Some(Token::Ident(name)) => {
+                if self.peek() != Some(&Token::LParen) {
+                    return Ok(Expr::Signal(name));
+                }
+                self.pos += 1;
+                // V(node) and I(device) name a signal, not a function call
+                if matches!(name.as_str(), "V" | "I" | "v" | "i") {
+                    let arg = match self.next() {
+                        Some(Token::Ident(arg)) => arg,
+                        _ => return Err(ExprError::at("expected a node or device name", offset)),
+                    };
+                    self.expect(Token::RParen, "')'")?;
+                    return Ok(Expr::Signal(format!("{}({arg})", name.to_uppercase())));
+                }
+                let mut args = vec![];
+                if self.peek() != Some(&Token::RParen) {
+                    args.push(self.sum()?);
+                    while self.peek() == Some(&Token::Comma) {
+                        self.pos += 1;
+                        args.push(self.sum()?);
+                    }
+                }
+                self.expect(Token::RParen, "')' or ','")?;
+                Ok(Expr::Call(name, args))
+            }
+            _ => Err(ExprError::at("expected a value", offset)),
+        }
+    }
+}
+
+/// Intermediate result: constants are broadcast when combined with waveforms
+enum Value {
+    Scalar(f64),
+    /// one value per point of the common x grid
+    Wave(Vec<f64>),
+}
+
+impl Value {
+    fn into_wave(self, len: usize) -> Vec<f64> {
+        match self {
+            Value::Scalar(v) => vec![v; len],
+            Value::Wave(w) => w,
+        }
+    }
+
+    fn map(self, f: impl Fn(f64) -> f64) -> Value {
+        match self {
+            Value::Scalar(v) => Value::Scalar(f(v)),
+            Value::Wave(w) => Value::Wave(w.into_iter().map(f).collect()),
+        }
+    }
+}
+
+/// Union of the x values of sorted traces, within the x range they all cover
+pub fn common_grid(sources: &[&[(f64, f64)]]) -> Vec<f64> {
+    let start = sources
+        .iter()
+        .filter_map(|v| v.first())
+        .fold(f64::NEG_INFINITY, |a, (x, _)| a.max(*x));
+    let end = sources
+        .iter()
+        .filter_map(|v| v.last())
+        .fold(f64::INFINITY, |a, (x, _)| a.min(*x));
+    let mut xs: Vec<f64> = sources
+        .iter()
+        .flat_map(|v| v.iter().map(|(x, _)| *x))
+        .filter(|x| (start..=end).contains(x))
+        .collect();
+    xs.sort_by(f64::total_cmp);
+    xs.dedup();
+    xs
+}
+
+/// Linear interpolation of sorted `values` at `x`, NaN outside of their x range
+pub fn interpolate(values: &[(f64, f64)], x: f64) -> f64 {
+    let i = values.partition_point(|(vx, _)| *vx < x);
+    match (values.get(i), i.checked_sub(1).map(|j| values[j])) {
+        (Some((x1, y1)), _) if *x1 == x => *y1,
+        (Some((x1, y1)), Some((x0, y0))) => y0 + (x - x0) / (x1 - x0) * (y1 - y0),
+        _ => f64::NAN,
+    }
+}
+
+/// Cumulative trapezoidal integral over the grid
+fn cumulative_integral(xs: &[f64], ys: &[f64]) -> Vec<f64> {
+    let mut sum = 0.0;
+    let mut result = Vec::with_capacity(xs.len());
+    for i in 0..xs.len() {
+        if i > 0 {
+            sum += 0.5 * (ys[i] + ys[i - 1]) * (xs[i] - xs[i - 1]);
+        }
+        result.push(sum);
+    }
+    result
+}
+
+/// Central differences inside, one-sided at the ends
+fn derivative(xs: &[f64], ys: &[f64]) -> Vec<f64> {
+    let n = xs.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (i.saturating_sub(1), (i + 1).min(n - 1));
+            if b > a {
+                (ys[b] - ys[a]) / (xs[b] - xs[a])
+            } else {
+                f64::NAN
+            }
+        })
+        .collect()
+}
+
+/// Trailing moving average over an x window, from the running integral so non-uniform
+/// grids are weighted correctly. Near the start the window is shortened.
+fn moving_average(xs: &[f64], ys: &[f64], window: f64) -> Vec<f64> {
+    let integral = cumulative_integral(xs, ys);
+    let running: Vec<_> = xs.iter().copied().zip(integral.iter().copied()).collect();
+    xs.iter()
+        .zip(&integral)
+        .zip(ys)
+        .map(|((x, i1), y)| {
+            let x0 = (x - window).max(xs[0]);
+            if *x > x0 {
+                (i1 - interpolate(&running, x0)) / (x - x0)
+            } else {
+                *y
+            }
+        })
+        .collect()
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, ExprError> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            pos: 0,
+            end: text.len(),
+        };
+        let expr = parser.sum()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(ExprError::at("unexpected input", parser.offset()));
+        }
+        Ok(expr)
+    }
+
+    /// Names of all traces the expression refers to
+    pub fn signals(&self) -> Vec<String> {
+        let mut names = vec![];
+        self.collect_signals(&mut names);
+        names
+    }
+
+    fn collect_signals(&self, names: &mut Vec<String>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Signal(name) => {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+            Expr::Neg(e) => e.collect_signals(names),
+            Expr::Binary(_, a, b) => {
+                a.collect_signals(names);
+                b.collect_signals(names);
+            }
+            Expr::Call(_, args) => args.iter().for_each(|a| a.collect_signals(names)),
+        }
+    }
+
+    /// Evaluate on the common grid of all referenced traces: the union of their x
+    /// values within the x range they all cover. `lookup` resolves a trace name to
+    /// the (x-sorted) values of the trace, see `signal_names`.
+    pub fn evaluate<'a>(
+        &self,
+        lookup: &dyn Fn(&str) -> Option<&'a [(f64, f64)]>,
+    ) -> Result<Vec<(f64, f64)>, ExprError> {
+        let mut sources = vec![];
+        for name in self.signals() {
+            let values = signal_names(&name)
+                .find_map(lookup)
+                .ok_or_else(|| ExprError::new(format!("unknown trace '{name}'")))?;
+            // only copied if there are gaps to drop
+            let finite = |(x, y): &(f64, f64)| x.is_finite() && y.is_finite();
+            let values = match values.iter().all(finite) {
+                true => Cow::Borrowed(values),
+                false => Cow::Owned(values.iter().copied().filter(finite).collect()),
+            };
+            if values.windows(2).any(|w| w[1].0 < w[0].0) {
+                return Err(ExprError::new(format!("trace '{name}' is not sorted by x")));
+            }
+            sources.push((name, values));
+        }
+        if sources.is_empty() {
+            return Err(ExprError::new("expression does not reference any trace"));
+        }
+
+        let values: Vec<_> = sources.iter().map(|(_, v)| &v[..]).collect();
+        let xs = common_grid(&values);
+        if xs.is_empty() {
+            return Err(ExprError::new("the traces have no common x range"));
+        }
+
+        let ys = self.eval(&xs, &sources)?.into_wave(xs.len());
+        Ok(xs.into_iter().zip(ys).collect())
+    }
+
+    fn eval(&self, xs: &[f64], sources: &[Source]) -> Result<Value, ExprError> {
+        Ok(match self {
+            Expr::Number(v) => Value::Scalar(*v),
+            Expr::Signal(name) => {
+                let (_, values) = sources.iter().find(|(n, _)| n == name).unwrap();
+                Value::Wave(xs.iter().map(|x| interpolate(values, *x)).collect())
+            }
+            Expr::Neg(e) => e.eval(xs, sources)?.map(|v| -v),
+            Expr::Binary(op, a, b) => {
+                let f: fn(f64, f64) -> f64 = match op {
+                    BinaryOp::Add => |a, b| a + b,
+                    BinaryOp::Sub => |a, b| a - b,
+                    BinaryOp::Mul => |a, b| a * b,
+                    BinaryOp::Div => |a, b| a / b,
+                    BinaryOp::Pow => f64::powf,
+                };
+                match (a.eval(xs, sources)?, b.eval(xs, sources)?) {
+                    (Value::Scalar(a), Value::Scalar(b)) => Value::Scalar(f(a, b)),
+                    (Value::Scalar(a), Value::Wave(b)) => {
+                        Value::Wave(b.iter().map(|b| f(a, *b)).collect())
+                    }
+                    (Value::Wave(a), Value::Scalar(b)) => {
+                        Value::Wave(a.iter().map(|a| f(*a, b)).collect())
+                    }
+                    (Value::Wave(a), Value::Wave(b)) => {
+                        Value::Wave(a.iter().zip(&b).map(|(a, b)| f(*a, *b)).collect())
+                    }
+                }
+            }
+            Expr::Call(name, args) => {
+                let arity = |n: usize| {
+                    if args.len() == n {
+                        Ok(())
+                    } else {
+                        Err(ExprError::new(format!("{name}() takes {n} argument(s)")))
+                    }
+                };
+                let pointwise = |f: fn(f64) -> f64| -> Result<Value, ExprError> {
+                    arity(1)?;
+                    Ok(args[0].eval(xs, sources)?.map(f))
+                };
+                match name.as_str() {
+                    "abs" => pointwise(f64::abs)?,
+                    "sqrt" => pointwise(f64::sqrt)?,
+                    "exp" => pointwise(f64::exp)?,
+                    "ln" => pointwise(f64::ln)?,
+                    "log10" => pointwise(f64::log10)?,
+                    "sin" => pointwise(f64::sin)?,
+                    "cos" => pointwise(f64::cos)?,
+                    "db" => pointwise(|v| 20.0 * v.abs().log10())?,
+                    "derivative" => {
+                        arity(1)?;
+                        let ys = args[0].eval(xs, sources)?.into_wave(xs.len());
+                        Value::Wave(derivative(xs, &ys))
+                    }
+                    "integral" => {
+                        arity(1)?;
+                        let ys = args[0].eval(xs, sources)?.into_wave(xs.len());
+                        Value::Wave(cumulative_integral(xs, &ys))
+                    }
+                    "avg" => {
+                        arity(2)?;
+                        let ys = args[0].eval(xs, sources)?.into_wave(xs.len());
+                        let Value::Scalar(window) = args[1].eval(xs, sources)? else {
+                            return Err(ExprError::new("avg() window must be a constant"));
+                        };
+                        Value::Wave(moving_average(xs, &ys, window))
+                    }
+                    _ => return Err(ExprError::new(format!("unknown function '{name}'"))),
+                }
+            }
+        })
+    }
+}
+
+/// Name and finite values of a trace an expression refers to
+type Source<'a> = (String, Cow<'a, [(f64, f64)]>);
+
+/// Trace names a signal refers to, in the order they are looked up: `V(out)` also
+/// matches a trace that is just called `out`
+pub fn signal_names(name: &str) -> impl Iterator<Item = &str> {
+    let inner = name
+        .strip_suffix(')')
+        .and_then(|n| n.split_once('('))
+        .map(|(_, inner)| inner);
+    std::iter::once(name).chain(inner)
+}
+
+/// A trace computed from an expression, re-evaluated whenever its sources change
+#[derive(Clone, Debug)]
+pub struct Derived {
+    pub expr: Expr,
+    pub text: String,
+    pub name: String,
+    /// `Trace::revision` of each source (None if missing) at the last evaluation
+    pub revisions: Vec<Option<u64>>,
+}
+
+impl Derived {
+    pub fn new(text: &str, name: Option<&str>) -> Result<Self, ExprError> {
+        Ok(Self {
+            expr: Expr::parse(text)?,
+            text: text.to_owned(),
+            name: name.unwrap_or(text).to_owned(),
+            revisions: vec![],
+        })
+    }
+
+    /// Evaluate into a new line trace
+    pub fn trace<'a>(
+        &self,
+        lookup: &dyn Fn(&str) -> Option<&'a [(f64, f64)]>,
+    ) -> Result<Trace, ExprError> {
+        Ok(Trace::new(self.expr.evaluate(lookup)?, &self.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Look up `x`, sampled at 0, 1 and 2, and `out` (constant 1)
+    fn lookup(name: &str) -> Option<&'static [(f64, f64)]> {
+        match name {
+            "x" => Some(&[(0.0, 0.0), (1.0, 1.0), (2.0, 4.0)]),
+            "out" => Some(&[(0.0, 1.0), (2.0, 1.0)]),
+            _ => None,
+        }
+    }
+
+    fn evaluate(text: &str) -> Result<Vec<f64>, ExprError> {
+        let values = Expr::parse(text)?.evaluate(&lookup)?;
+        Ok(values.into_iter().map(|(_, y)| y).collect())
+    }
+
+    /// Value of a constant expression
+    fn value(text: &str) -> f64 {
+        evaluate(&format!("V(out) * ({text})")).unwrap()[0]
+    }
+
+    fn error(text: &str) -> ExprError {
+        evaluate(text).unwrap_err()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(value("1 + 2 * 3"), 7.0);
+        assert_eq!(value("(1 + 2) * 3"), 9.0);
+        assert_eq!(value("10 - 4 - 3"), 3.0);
+        assert_eq!(value("8 / 4 / 2"), 1.0);
+        assert_eq!(value("2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(value("2 * 3 ^ 2"), 18.0);
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(value("-2 ^ 2"), -4.0);
+        assert_eq!(value("2 * -3"), -6.0);
+        assert_eq!(value("2 ^ -1"), 0.5);
+        assert_eq!(value("--3"), 3.0);
+        assert_eq!(value("-(1 - 3)"), 2.0);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(value("1.5e3"), 1500.0);
+        assert_eq!(value("1meg"), 1e6);
+        assert_eq!(value("2k"), 2000.0);
+        assert_eq!(value("10ns"), 10.0 * 1e-9);
+        assert_eq!(value("1m"), 1e-3);
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(value("abs(-2)"), 2.0);
+        assert_eq!(value("sqrt(16)"), 4.0);
+        assert_eq!(value("db(10)"), 20.0);
+        assert_eq!(evaluate("derivative(x)").unwrap(), vec![1.0, 2.0, 3.0]);
+        assert_eq!(evaluate("integral(x)").unwrap(), vec![0.0, 0.5, 3.0]);
+        assert_eq!(evaluate("avg(x, 1)").unwrap(), vec![0.0, 0.5, 2.5]);
+    }
+
+    #[test]
+    fn signals() {
+        let expr = Expr::parse("V(out) - x * v(out)").unwrap();
+        assert_eq!(expr.signals(), vec!["V(out)".to_owned(), "x".to_owned()]);
+        // on the union of the x values
+        assert_eq!(evaluate("x + out").unwrap(), vec![1.0, 2.0, 5.0]);
+    }
+
+    #[test]
+    fn unknown_names() {
+        assert_eq!(error("x + y").message, "unknown trace 'y'");
+        assert_eq!(error("V(in)").message, "unknown trace 'V(in)'");
+        assert_eq!(error("foo(x)").message, "unknown function 'foo'");
+        assert_eq!(error("sin(x, x)").message, "sin() takes 1 argument(s)");
+        assert_eq!(
+            error("avg(x, x)").message,
+            "avg() window must be a constant"
+        );
+        assert_eq!(
+            error("1 + 2").message,
+            "expression does not reference any trace"
+        );
+    }
+
+    #[test]
+    fn error_positions() {
+        let at = |text: &str| {
+            let e = Expr::parse(text).unwrap_err();
+            (e.message, e.position)
+        };
+        assert_eq!(at("1 + * 2"), ("expected a value".to_owned(), Some(4)));
+        assert_eq!(at("(1 + 2"), ("expected ')'".to_owned(), Some(6)));
+        assert_eq!(
+            at("1 $ 2"),
+            ("unexpected character '$'".to_owned(), Some(2))
+        );
+        assert_eq!(at("1 2"), ("unexpected input".to_owned(), Some(2)));
+        assert_eq!(at("sin(x,"), ("expected a value".to_owned(), Some(6)));
+        assert_eq!(
+            at("V(1)"),
+            ("expected a node or device name".to_owned(), Some(0))
+        );
+        // evaluation errors have no position
+        assert_eq!(error("x + y").position, None);
+    }
+}
@@ -0,0 +1,381 @@
+use crate::annotation::{Annotation, AnnotationStyle, Shape};
+use crate::cairo_utils::format_number;
+use crate::colormap::Colormap;
+use crate::image::Image;
+use crate::measure::{crossings, levels};
+use crate::trace::{Trace, XOrder};
+
+/// Most unit intervals folded into an eye, so a unit interval far too small for the
+/// trace can't stall the caller
+const MAX_UNIT_INTERVALS: f64 = 100_000.0;
+
+/// How the trace is folded into an eye
+#[derive(Clone, Copy, Debug)]
+pub struct EyeConfig {
+    /// nominal unit interval (bit time)
+    pub ui: f64,
+    /// time of a data transition, used as the fold phase without clock recovery
+    pub offset: f64,
+    /// refine unit interval and phase from the data transitions
+    pub clock_recovery: bool,
+    /// number of unit intervals shown
+    pub spans: usize,
+    /// density image resolution
+    pub cols: usize,
+    pub rows: usize,
+}
+
+impl EyeConfig {
+    pub fn new(ui: f64) -> Self {
+        Self {
+            ui,
+            offset: 0.0,
+            clock_recovery: true,
+            spans: 2,
+            cols: 200,
+            rows: 150,
+        }
+    }
+}
+
+/// Eye opening and crossing statistics
+#[derive(Clone, Debug)]
+pub struct EyeMeasurements {
+    /// unit interval and transition phase after clock recovery
+    pub ui: f64,
+    pub phase: f64,
+    /// decision threshold, halfway between the settled levels
+    pub threshold: f64,
+    /// highest zero and lowest one at the eye center
+    pub opening: (f64, f64),
+    /// vertical opening at the eye center
+    pub height: f64,
+    /// horizontal opening at the threshold: UI minus the peak-to-peak jitter
+    pub width: f64,
+    /// deviation of the threshold crossings from the recovered clock
+    pub jitter_rms: f64,
+    pub jitter_pp: f64,
+}
+
+/// A time-domain trace folded modulo the unit interval. Transitions are placed at
+/// half a UI from the left edge (and every UI after that), so with two spans a full
+/// eye is centered in the image.
+pub struct Eye {
+    values: Vec<(f64, f64)>,
+    config: EyeConfig,
+    pub measurements: EyeMeasurements,
+}
+
+/// Least squares fit of transition times t ≈ phase + n·ui, with n the nearest UI index
+fn recover_clock(transitions: &[f64], ui: f64, offset: f64) -> (f64, f64) {
+    let Some(first) = transitions.first() else {
+        return (ui, offset);
+    };
+    let n: Vec<f64> = transitions
+        .iter()
+        .map(|t| ((t - first) / ui).round())
+        .collect();
+    let count = n.len() as f64;
+    let (mean_n, mean_t) = (
+        n.iter().sum::<f64>() / count,
+        transitions.iter().sum::<f64>() / count,
+    );
+    let var_n: f64 = n.iter().map(|n| (n - mean_n).powi(2)).sum();
+    let fitted_ui = if var_n > 0.0 {
+        n.iter()
+            .zip(transitions)
+            .map(|(n, t)| (n - mean_n) * (t - mean_t))
+            .sum::<f64>()
+            / var_n
+    } else {
+        ui
+    };
+    (fitted_ui, mean_t - fitted_ui * mean_n)
+}
+
+/// Linear interpolation of sorted `values` at `t`, None outside of their range
+fn interpolate(values: &[(f64, f64)], t: f64) -> Option<f64> {
+    let i = values.partition_point(|(x, _)| *x < t);
+    let (x1, y1) = *values.get(i)?;
+    if x1 == t {
+        return Some(y1);
+    }
+    let (x0, y0) = values[i.checked_sub(1)?];
+    Some(y0 + (t - x0) / (x1 - x0) * (y1 - y0))
+}
+
+impl Eye {
+    /// Fold a trace sorted by time. Returns None if the trace isn't sorted, there are
+    /// fewer than two finite samples, or the unit interval (after clock recovery) is not
+    /// positive or would fold more than `MAX_UNIT_INTERVALS` of them.
+    pub fn new(trace: &Trace, config: EyeConfig) -> Option<Self> {
+        if trace.order != XOrder::Sorted {
+            return None;
+        }
+        let values: Vec<_> = trace
+            .values
+            .iter()
+            .copied()
+            .filter(|(x, y)| x.is_finite() && y.is_finite())
+            .collect();
+        let (t0, t1) = (values.first()?.0, values.last()?.0);
+        let too_many = |ui: f64| !(ui > 0.0 && (t1 - t0) / ui <= MAX_UNIT_INTERVALS);
+        if values.len() < 2 || too_many(config.ui) {
+            return None;
+        }
+        let (min, max) = values
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), (_, y)| {
+                (min.min(*y), max.max(*y))
+            });
+        let (base, top) = levels(&values, min, max);
+        let threshold = 0.5 * (base + top);
+        let transitions: Vec<f64> = crossings(&values, threshold)
+            .into_iter()
+            .map(|(t, _)| t)
+            .collect();
+
+        let (ui, phase) = if config.clock_recovery {
+            recover_clock(&transitions, config.ui, config.offset)
+        } else {
+            (config.ui, config.offset)
+        };
+        if too_many(ui) {
+            return None;
+        }
+
+        // crossing deviation from the nearest recovered clock edge
+        let deviations: Vec<f64> = transitions
+            .iter()
+            .map(|t| {
+                let d = (t - phase) / ui;
+                (d - d.round()) * ui
+            })
+            .collect();
+        let (jitter_rms, jitter_pp) = if deviations.is_empty() {
+            (0.0, 0.0)
+        } else {
+            let n = deviations.len() as f64;
+            let mean = deviations.iter().sum::<f64>() / n;
+            let rms = (deviations.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / n).sqrt();
+            let (lo, hi) = deviations
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), d| {
+                    (lo.min(*d), hi.max(*d))
+                });
+            (rms, hi - lo)
+        };
+
+        // sample each bit at the eye center, half a UI after its transition
+        let first_bit = ((t0 - phase) / ui).ceil() as i64;
+        let last_bit = ((t1 - phase) / ui).floor() as i64;
+        let (mut lowest_one, mut highest_zero) = (f64::INFINITY, f64::NEG_INFINITY);
+        for bit in first_bit..last_bit {
+            let Some(v) = interpolate(&values, phase + (bit as f64 + 0.5) * ui) else {
+                continue;
+            };
+            if v >= threshold {
+                lowest_one = lowest_one.min(v);
+            } else {
+                highest_zero = highest_zero.max(v);
+            }
+        }
+        let opening = if lowest_one.is_finite() && highest_zero.is_finite() {
+            (highest_zero, lowest_one)
+        } else {
+            (threshold, threshold)
+        };
+
+        Some(Self {
+            values,
+            config,
+            measurements: EyeMeasurements {
+                ui,
+                phase,
+                threshold,
+                opening,
+                height: (opening.1 - opening.0).max(0.0),
+                width: (ui - jitter_pp).max(0.0),
+                jitter_rms,
+                jitter_pp,
+            },
+        })
+    }
+
+    /// Time within the eye window, transitions land at half a UI
+    fn fold(&self, t: f64) -> f64 {
+        let ui = self.measurements.ui;
+        (t - self.measurements.phase + 0.5 * ui).rem_euclid(self.config.spans as f64 * ui)
+    }
+
+    /// Density image: each cell counts how often the waveform passes through it.
+    /// Empty cells are NaN (transparent).
+    pub fn image(&self, name: &str) -> Image {
+        let (cols, rows) = (self.config.cols.max(1), self.config.rows.max(1));
+        let width = self.config.spans as f64 * self.measurements.ui;
+        let (min, max) = self
+            .values
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), (_, y)| {
+                (min.min(*y), max.max(*y))
+            });
+        // a little headroom above and below the waveform
+        let pad = 0.05 * (max - min).max(f64::EPSILON);
+        let (y0, y1) = (min - pad, max + pad);
+
+        let col_of = |tau: f64| tau / width * cols as f64;
+        let row_of = |y: f64| (y - y0) / (y1 - y0) * rows as f64;
+        let mut counts = vec![0u32; cols * rows];
+        for w in self.values.windows(2) {
+            let ((ta, ya), (tb, yb)) = (w[0], w[1]);
+            let (ca, ra) = (col_of(self.fold(ta)), row_of(ya));
+            // unfolded end point, a segment leaving the window continues on the other side
+            let cb = ca + col_of(tb - ta);
+            let rb = row_of(yb);
+            // a segment wrapping around the window more than once is only sampled coarsely
+            let steps = (cb - ca).abs().max((rb - ra).abs()).ceil();
+            let steps = steps.clamp(1.0, (2 * (cols + rows)) as f64) as usize;
+            let mut last = None;
+            for s in 0..=steps {
+                let f = s as f64 / steps as f64;
+                let c = (ca + f * (cb - ca)).rem_euclid(cols as f64) as usize;
+                let r = (ra + f * (rb - ra)) as usize;
+                let cell = (c.min(cols - 1), r.min(rows - 1));
+                // count each cell once per segment
+                if last != Some(cell) {
+                    counts[cell.1 * cols + cell.0] += 1;
+                    last = Some(cell);
+                }
+            }
+        }
+
+        let values = counts
+            .iter()
+            .map(|c| if *c == 0 { f64::NAN } else { *c as f64 })
+            .collect();
+        let mut image = Image::new(values, cols, rows, (0.0, width), (y0, y1), name)
+            .expect("one count per cell");
+        image.colormap = Colormap::Magma;
+        image
+    }
+}
+
+impl EyeMeasurements {
+    /// (name, formatted value) rows for a measurement table
+    pub fn table(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("UI", format_number(self.ui)),
+            ("Threshold", format_number(self.threshold)),
+            ("Height", format_number(self.height)),
+            ("Width", format_number(self.width)),
+            ("Jitter RMS", format_number(self.jitter_rms)),
+            ("Jitter p-p", format_number(self.jitter_pp)),
+        ]
+    }
+
+    /// Arrows across the first eye of the image, for its height and width
+    pub fn annotations(&self) -> Vec<Annotation> {
+        let style = AnnotationStyle {
+            color: (0.2, 0.9, 0.9),
+            ..AnnotationStyle::default()
+        };
+        let center = self.ui;
+        let half_width = 0.5 * self.width;
+        vec![
+            Annotation::new(Shape::Arrow {
+                from: (center, self.opening.0),
+                to: (center, self.opening.1),
+            })
+            .with_label(&format!("height {}", format_number(self.height)))
+            .with_style(style.clone()),
+            Annotation::new(Shape::Arrow {
+                from: (center - half_width, self.threshold),
+                to: (center + half_width, self.threshold),
+            })
+            .with_label(&format!("width {}", format_number(self.width)))
+            .with_style(style),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// NRZ data with a unit interval of 1, transitions 0.2 UI long centered at
+    /// 0.3 + k (plus `jitter(k)`), levels 0 and 1
+    fn nrz(jitter: impl Fn(usize) -> f64) -> Trace {
+        // pseudo-random bits from a 7 bit LFSR
+        let mut state = 0x5bu8;
+        let bits: Vec<bool> = (0..200)
+            .map(|_| {
+                let bit = ((state >> 6) ^ (state >> 5)) & 1;
+                state = ((state << 1) | bit) & 0x7f;
+                bit == 1
+            })
+            .collect();
+        let level = |b: bool| if b { 1.0 } else { 0.0 };
+        let mut values = vec![(0.0, level(bits[0]))];
+        for k in 1..bits.len() {
+            if bits[k] != bits[k - 1] {
+                let t = 0.3 + k as f64 + jitter(k);
+                values.push((t - 0.1, level(bits[k - 1])));
+                values.push((t + 0.1, level(bits[k])));
+            }
+        }
+        values.push((bits.len() as f64, level(bits[bits.len() - 1])));
+        Trace::new(values, "data")
+    }
+
+    fn assert_close(value: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (value - expected).abs() <= tolerance,
+            "{value} is not {expected} ± {tolerance}"
+        );
+    }
+
+    #[test]
+    fn recovers_the_clock() {
+        // a guess off by 0.1% drifts by 0.2 UI over the 200 bits
+        let eye = Eye::new(&nrz(|_| 0.0), EyeConfig::new(1.001)).unwrap();
+        let m = &eye.measurements;
+        assert_close(m.ui, 1.0, 1e-9);
+        assert_close((m.phase - 0.3).rem_euclid(1.0), 0.0, 1e-9);
+        assert_close(m.threshold, 0.5, 1e-9);
+    }
+
+    #[test]
+    fn clean_eye_is_fully_open() {
+        let eye = Eye::new(&nrz(|_| 0.0), EyeConfig::new(1.0)).unwrap();
+        let m = &eye.measurements;
+        assert_eq!(m.opening, (0.0, 1.0));
+        assert_close(m.height, 1.0, 1e-9);
+        assert_close(m.width, 1.0, 1e-9);
+        assert_close(m.jitter_pp, 0.0, 1e-9);
+    }
+
+    #[test]
+    fn jitter_narrows_the_eye() {
+        let jitter = |k: usize| if k.is_multiple_of(2) { 0.05 } else { -0.05 };
+        // on the nominal clock, so the deviations are exactly the jitter
+        let config = EyeConfig {
+            offset: 0.3,
+            clock_recovery: false,
+            ..EyeConfig::new(1.0)
+        };
+        let eye = Eye::new(&nrz(jitter), config).unwrap();
+        let m = &eye.measurements;
+        assert_close(m.jitter_pp, 0.1, 1e-9);
+        assert_close(m.jitter_rms, 0.05, 0.005);
+        assert_close(m.width, 0.9, 1e-9);
+        assert_close(m.height, 1.0, 1e-9);
+    }
+
+    #[test]
+    fn rejects_unsorted_traces_and_tiny_unit_intervals() {
+        let unsorted = Trace::new(vec![(0.0, 0.0), (2.0, 1.0), (1.0, 0.0)], "loop");
+        assert!(Eye::new(&unsorted, EyeConfig::new(1.0)).is_none());
+        assert!(Eye::new(&nrz(|_| 0.0), EyeConfig::new(1e-6)).is_none());
+        assert!(Eye::new(&nrz(|_| 0.0), EyeConfig::new(0.0)).is_none());
+    }
+}
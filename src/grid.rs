@@ -1,61 +1,147 @@
-use crate::{axis::Axis, cairo_utils::PixelContext};
+use crate::{
+    axis::{Axis, Ticks},
+    cairo_utils::PixelContext,
+    theme::{Color, Theme},
+};
 
-pub struct Grid {}
+/// Which lines of one level (major or minor) are drawn, and how
+#[derive(Clone, Debug, PartialEq)]
+pub struct GridLines {
+    /// vertical lines at the x ticks
+    pub x: bool,
+    /// horizontal lines at the y ticks
+    pub y: bool,
+    /// the grid color of the theme if None
+    pub color: Option<Color>,
+    pub alpha: f64,
+    pub width: f64,
+    /// dash lengths, solid lines if empty
+    pub dash: Vec<f64>,
+}
 
-impl Grid {
-    pub fn draw(
-        &self,
-        cx: &gtk::cairo::Context,
-        rect: gtk::cairo::Rectangle,
-        primary_x: &Axis,
-        primary_y: &Axis,
-    ) {
-        cx.set_line_width(1.0);
+impl Default for GridLines {
+    fn default() -> Self {
+        Self {
+            x: true,
+            y: true,
+            color: None,
+            alpha: 1.0,
+            width: 1.0,
+            dash: vec![],
+        }
+    }
+}
 
-        // move to lower left corner
-        PixelContext::new(cx).move_to(rect.x(), rect.y() + rect.height());
+impl GridLines {
+    fn hidden() -> Self {
+        Self {
+            x: false,
+            y: false,
+            ..Self::default()
+        }
+    }
+}
 
-        // save start position
-        let start_point = cx.current_point().unwrap();
+/// Whether the grid is drawn below the images and traces or on top of them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GridLayer {
+    Below,
+    Above,
+}
 
-        let (x_ticks_major, x_ticks_minor, _) = primary_x
-            .locator
-            .get_ticks(primary_x.range, Some(50.0 / rect.width()));
+/// Axis whose ticks the grid lines follow
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GridAxis {
+    Primary,
+    /// the secondary axis if the Axes has one, the primary one otherwise
+    Secondary,
+}
 
-        let (y_ticks_major, y_ticks_minor, _) = primary_y
-            .locator
-            .get_ticks(primary_y.range, Some(50.0 / rect.height()));
+/// Ticks of the axes a grid follows, as drawn with their labels
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GridTicks {
+    pub x: Ticks,
+    pub y: Ticks,
+}
 
-        // minor
-        cx.set_source_rgb(0.925, 0.925, 0.925);
-        for t in x_ticks_minor {
-            cx.move_to(start_point.0, start_point.1);
-            let t_01 = primary_x.data_to_axis(t);
-            PixelContext::new(cx).rel_move_to(t_01 * rect.width(), 0.0);
-            PixelContext::new(cx).rel_line_to(0.0, -rect.height());
-        }
-        for t in y_ticks_minor {
-            cx.move_to(start_point.0, start_point.1);
-            let t_01 = primary_y.data_to_axis(t);
-            PixelContext::new(cx).rel_move_to(0.0, -t_01 * rect.height());
-            PixelContext::new(cx).rel_line_to(rect.width(), 0.0);
+#[derive(Clone, Debug, PartialEq)]
+pub struct Grid {
+    pub major: GridLines,
+    pub minor: GridLines,
+    pub layer: GridLayer,
+    pub x_axis: GridAxis,
+    pub y_axis: GridAxis,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self {
+            major: GridLines::default(),
+            minor: GridLines::default(),
+            layer: GridLayer::Below,
+            x_axis: GridAxis::Primary,
+            y_axis: GridAxis::Primary,
         }
-        cx.stroke().unwrap();
-
-        // major
-        cx.set_source_rgb(0.8, 0.8, 0.8);
-        for t in x_ticks_major {
-            cx.move_to(start_point.0, start_point.1);
-            let t_01 = primary_x.data_to_axis(t);
-            PixelContext::new(cx).rel_move_to(t_01 * rect.width(), 0.0);
-            PixelContext::new(cx).rel_line_to(0.0, -rect.height());
+    }
+}
+
+impl Grid {
+    /// No grid lines at all
+    pub fn hidden() -> Self {
+        Self {
+            major: GridLines::hidden(),
+            minor: GridLines::hidden(),
+            ..Self::default()
         }
-        for t in y_ticks_major {
-            cx.move_to(start_point.0, start_point.1);
-            let t_01 = primary_y.data_to_axis(t);
-            PixelContext::new(cx).rel_move_to(0.0, -t_01 * rect.height());
-            PixelContext::new(cx).rel_line_to(rect.width(), 0.0);
+    }
+
+    /// Draw the lines at the ticks of the two axes, e.g. returned by `Axis::draw`
+    pub fn draw(
+        &self,
+        cx: &gtk::cairo::Context,
+        rect: gtk::cairo::Rectangle,
+        (x_axis, x_ticks): (&Axis, &Ticks),
+        (y_axis, y_ticks): (&Axis, &Ticks),
+        theme: &Theme,
+    ) {
+        let minor = (&x_ticks.minor, &y_ticks.minor);
+        let major = (&x_ticks.major, &y_ticks.major);
+        let levels = [
+            (&self.minor, theme.grid_minor, minor),
+            (&self.major, theme.grid_major, major),
+        ];
+        for (lines, theme_color, (xs, ys)) in levels {
+            if !(lines.x || lines.y) {
+                continue;
+            }
+            // move to lower left corner
+            PixelContext::new(cx).move_to(rect.x(), rect.y() + rect.height());
+            // save start position
+            let start_point = cx.current_point().unwrap();
+
+            if lines.x {
+                for t in xs {
+                    cx.move_to(start_point.0, start_point.1);
+                    let t_01 = x_axis.data_to_axis(*t);
+                    PixelContext::new(cx).rel_move_to(t_01 * rect.width(), 0.0);
+                    PixelContext::new(cx).rel_line_to(0.0, -rect.height());
+                }
+            }
+            if lines.y {
+                for t in ys {
+                    cx.move_to(start_point.0, start_point.1);
+                    let t_01 = y_axis.data_to_axis(*t);
+                    PixelContext::new(cx).rel_move_to(0.0, -t_01 * rect.height());
+                    PixelContext::new(cx).rel_line_to(rect.width(), 0.0);
+                }
+            }
+            let (r, g, b) = lines.color.unwrap_or(theme_color);
+            cx.set_source_rgba(r, g, b, lines.alpha);
+            cx.set_line_width(lines.width);
+            cx.set_dash(&lines.dash, 0.0);
+            cx.stroke().unwrap();
         }
-        cx.stroke().unwrap();
+        cx.set_dash(&[], 0.0);
+        cx.set_line_width(1.0);
     }
 }
@@ -0,0 +1,224 @@
+use gtk::cairo::{Context, Extend, Filter, Format, ImageSurface, Rectangle};
+
+use crate::axis::Axis;
+use crate::colormap::Colormap;
+use crate::trace::LengthError;
+
+/// How grid values are interpolated when the image is scaled
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sampling {
+    Nearest,
+    Bilinear,
+}
+
+/// A regular grid of values drawn through a colormap (spectrograms, 2-D sweeps...)
+pub struct Image {
+    pub name: String,
+    /// row-major values, row 0 is at the bottom (`y_range.0`)
+    pub values: Vec<f64>,
+    pub cols: usize,
+    pub rows: usize,
+    /// data coordinates of the outer edges of the grid
+    pub x_range: (f64, f64),
+    pub y_range: (f64, f64),
+    pub colormap: Colormap,
+    pub sampling: Sampling,
+    /// values mapped to the ends of the colormap
+    pub value_range: (f64, f64),
+}
+
+/// Grid cell under the cursor
+#[derive(Clone, Debug)]
+pub struct ImagePoint {
+    /// index of the image in its Axes
+    pub image: usize,
+    pub col: usize,
+    pub row: usize,
+    pub x: f64,
+    pub y: f64,
+    pub value: f64,
+}
+
+impl Image {
+    /// `values` holds `cols * rows` cells, row by row
+    pub fn new(
+        values: Vec<f64>,
+        cols: usize,
+        rows: usize,
+        x_range: (f64, f64),
+        y_range: (f64, f64),
+        name: &str,
+    ) -> Result<Self, LengthError> {
+        if values.len() != cols * rows {
+            return Err(LengthError {
+                expected: cols * rows,
+                found: values.len(),
+            });
+        }
+        let (min, max) = values
+            .iter()
+            .filter(|v| v.is_finite())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+                (min.min(*v), max.max(*v))
+            });
+        Ok(Self {
+            name: name.to_owned(),
+            values,
+            cols,
+            rows,
+            x_range,
+            y_range,
+            colormap: Colormap::Viridis,
+            sampling: Sampling::Nearest,
+            value_range: if min <= max { (min, max) } else { (0.0, 1.0) },
+        })
+    }
+
+    pub fn bbox(&self) -> Rectangle {
+        Rectangle::new(
+            self.x_range.0,
+            self.y_range.0,
+            self.x_range.1 - self.x_range.0,
+            self.y_range.1 - self.y_range.0,
+        )
+    }
+
+    /// Fractional cell coordinates of a data point (cell centers are at n + 0.5)
+    fn to_cell(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            (x - self.x_range.0) / (self.x_range.1 - self.x_range.0) * self.cols as f64,
+            (y - self.y_range.0) / (self.y_range.1 - self.y_range.0) * self.rows as f64,
+        )
+    }
+
+    /// Cell containing the data point (x, y), or None outside of the image
+    pub fn cell_at(&self, x: f64, y: f64) -> Option<(usize, usize)> {
+        let (cx, cy) = self.to_cell(x, y);
+        let inside = (0.0..self.cols as f64).contains(&cx) && (0.0..self.rows as f64).contains(&cy);
+        inside.then_some((cx as usize, cy as usize))
+    }
+
+    pub fn value(&self, col: usize, row: usize) -> f64 {
+        self.values[row * self.cols + col]
+    }
+
+    /// Value at a data point, using the image's sampling mode
+    fn sample(&self, x: f64, y: f64) -> Option<f64> {
+        let (col, row) = self.cell_at(x, y)?;
+        let nearest = self.value(col, row);
+        match self.sampling {
+            Sampling::Nearest => Some(nearest),
+            Sampling::Bilinear => {
+                let (cx, cy) = self.to_cell(x, y);
+                let u = (cx - 0.5).clamp(0.0, (self.cols - 1) as f64);
+                let v = (cy - 0.5).clamp(0.0, (self.rows - 1) as f64);
+                let (c0, r0) = (u.floor() as usize, v.floor() as usize);
+                let (c1, r1) = ((c0 + 1).min(self.cols - 1), (r0 + 1).min(self.rows - 1));
+                let (fu, fv) = (u - c0 as f64, v - r0 as f64);
+                let bottom = self.value(c0, r0) * (1.0 - fu) + self.value(c1, r0) * fu;
+                let top = self.value(c0, r1) * (1.0 - fu) + self.value(c1, r1) * fu;
+                let result = bottom * (1.0 - fv) + top * fv;
+                // a NaN neighbour would spread into the whole cell
+                Some(if result.is_finite() { result } else { nearest })
+            }
+        }
+    }
+
+    /// Premultiplied ARGB32 pixel for a value, NaN is transparent
+    fn pixel(&self, v: f64) -> u32 {
+        if !v.is_finite() {
+            return 0;
+        }
+        let (lo, hi) = self.value_range;
+        let v_01 = if hi > lo { (v - lo) / (hi - lo) } else { 0.5 };
+        let (r, g, b) = self.colormap.color(v_01);
+        let byte = |c: f64| (c * 255.0).round() as u32;
+        0xff00_0000 | byte(r) << 16 | byte(g) << 8 | byte(b)
+    }
+
+    /// Draw into the chart rectangle (pixel coordinates). With two linear axes the grid
+    /// is scaled by cairo, otherwise it is resampled per pixel so log axes map correctly.
+    pub fn draw(&self, cx: &Context, chart: Rectangle, x_axis: &Axis, y_axis: &Axis) {
+        if self.cols == 0 || self.rows == 0 {
+            return;
+        }
+        if x_axis.is_linear() && y_axis.is_linear() {
+            self.draw_scaled(cx, chart, x_axis, y_axis);
+        } else {
+            self.draw_resampled(cx, chart, x_axis, y_axis);
+        }
+    }
+
+    fn draw_scaled(&self, cx: &Context, chart: Rectangle, x_axis: &Axis, y_axis: &Axis) {
+        let Ok(mut surface) =
+            ImageSurface::create(Format::ARgb32, self.cols as i32, self.rows as i32)
+        else {
+            return;
+        };
+        let stride = surface.stride() as usize;
+        {
+            let mut data = surface.data().unwrap();
+            for row in 0..self.rows {
+                // surface rows go top to bottom
+                let line = (self.rows - 1 - row) * stride;
+                for col in 0..self.cols {
+                    let px = self.pixel(self.value(col, row)).to_ne_bytes();
+                    data[line + 4 * col..line + 4 * col + 4].copy_from_slice(&px);
+                }
+            }
+        }
+
+        let left = chart.x() + chart.width() * x_axis.data_to_axis(self.x_range.0);
+        let right = chart.x() + chart.width() * x_axis.data_to_axis(self.x_range.1);
+        let top = chart.y() + chart.height() * (1.0 - y_axis.data_to_axis(self.y_range.1));
+        let bottom = chart.y() + chart.height() * (1.0 - y_axis.data_to_axis(self.y_range.0));
+
+        cx.save().unwrap();
+        cx.translate(left, top);
+        cx.scale(
+            (right - left) / self.cols as f64,
+            (bottom - top) / self.rows as f64,
+        );
+        cx.set_source_surface(&surface, 0.0, 0.0).unwrap();
+        let pattern = cx.source();
+        pattern.set_filter(match self.sampling {
+            Sampling::Nearest => Filter::Nearest,
+            Sampling::Bilinear => Filter::Bilinear,
+        });
+        // avoid fading into transparency at the image border
+        pattern.set_extend(Extend::Pad);
+        cx.rectangle(0.0, 0.0, self.cols as f64, self.rows as f64);
+        cx.fill().unwrap();
+        cx.restore().unwrap();
+    }
+
+    fn draw_resampled(&self, cx: &Context, chart: Rectangle, x_axis: &Axis, y_axis: &Axis) {
+        // one sample per device pixel of the target (HiDPI or export resolution)
+        let (scale_x, scale_y) = cx.target().device_scale();
+        let (chart_width, chart_height) = (chart.width() * scale_x, chart.height() * scale_y);
+        let (width, height) = (chart_width.ceil() as i32, chart_height.ceil() as i32);
+        let Ok(mut surface) = ImageSurface::create(Format::ARgb32, width.max(1), height.max(1))
+        else {
+            return;
+        };
+        surface.set_device_scale(scale_x, scale_y);
+        let stride = surface.stride() as usize;
+        {
+            let xs: Vec<_> = (0..width)
+                .map(|px| x_axis.axis_to_data((px as f64 + 0.5) / chart_width))
+                .collect();
+            let mut data = surface.data().unwrap();
+            for py in 0..height as usize {
+                let y = y_axis.axis_to_data(1.0 - (py as f64 + 0.5) / chart_height);
+                for (px, x) in xs.iter().enumerate() {
+                    let pixel = self.sample(*x, y).map_or(0, |v| self.pixel(v));
+                    let i = py * stride + 4 * px;
+                    data[i..i + 4].copy_from_slice(&pixel.to_ne_bytes());
+                }
+            }
+        }
+        cx.set_source_surface(&surface, chart.x(), chart.y())
+            .unwrap();
+        cx.paint().unwrap();
+    }
+}
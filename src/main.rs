@@ -1,12 +1,26 @@
 use gtk;
 
+mod annotation;
 mod axes;
 mod axis;
 mod cairo_utils;
+mod colormap;
+mod expr;
+mod eye;
 mod grid;
+mod image;
 mod locator;
+mod markup;
+mod mask;
+mod measure;
 mod plot;
 mod plot_component;
+mod render;
+mod session;
+mod spectrum;
+mod stream;
+mod theme;
+mod trace;
 mod window;
 // mod relmplot;
 
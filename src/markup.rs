@@ -0,0 +1,169 @@
+/// Greek letters and symbols available as `\name`
+const SYMBOLS: &[(&str, &str)] = &[
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ε"),
+    ("zeta", "ζ"),
+    ("eta", "η"),
+    ("theta", "θ"),
+    ("iota", "ι"),
+    ("kappa", "κ"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("nu", "ν"),
+    ("xi", "ξ"),
+    ("pi", "π"),
+    ("rho", "ρ"),
+    ("sigma", "σ"),
+    ("tau", "τ"),
+    ("upsilon", "υ"),
+    ("phi", "φ"),
+    ("chi", "χ"),
+    ("psi", "ψ"),
+    ("omega", "ω"),
+    ("Gamma", "Γ"),
+    ("Delta", "Δ"),
+    ("Theta", "Θ"),
+    ("Lambda", "Λ"),
+    ("Xi", "Ξ"),
+    ("Pi", "Π"),
+    ("Sigma", "Σ"),
+    ("Phi", "Φ"),
+    ("Psi", "Ψ"),
+    ("Omega", "Ω"),
+    ("deg", "°"),
+    ("pm", "±"),
+    ("times", "×"),
+    ("infty", "∞"),
+    ("sqrt", "√"),
+];
+
+/// Convert label markup to Pango markup. The label markup is:
+/// - `_{...}` subscript and `^{...}` superscript, e.g. `V_{out}` or `10^{-3}`
+/// - `\bf{...}` bold and `\it{...}` italic
+/// - `\mu`, `\Omega`... Greek letters and a few symbols (`\deg`, `\pm`, `\times`,
+///   `\infty`, `\sqrt`)
+/// - `\_`, `\^`, `\{`, `\}` and `\\` for the characters themselves
+///
+/// Everything else, including `_` and `^` not followed by `{` and unknown `\names`, is
+/// shown as it is, so plain names are safe to use as labels.
+pub fn to_pango(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    // closing tags of the open groups
+    let mut open: Vec<&str> = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let after = &rest[c.len_utf8()..];
+        rest = match c {
+            '_' | '^' if after.starts_with('{') => {
+                let (tag, close) = if c == '_' {
+                    ("<sub>", "</sub>")
+                } else {
+                    ("<sup>", "</sup>")
+                };
+                out.push_str(tag);
+                open.push(close);
+                &after[1..]
+            }
+            '}' if !open.is_empty() => {
+                out.push_str(open.pop().unwrap());
+                after
+            }
+            '\\' => {
+                let name_len = after
+                    .find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(after.len());
+                let (name, tail) = after.split_at(name_len);
+                match (name, tail.starts_with('{')) {
+                    ("bf", true) | ("it", true) => {
+                        let (tag, close) = if name == "bf" {
+                            ("<b>", "</b>")
+                        } else {
+                            ("<i>", "</i>")
+                        };
+                        out.push_str(tag);
+                        open.push(close);
+                        &tail[1..]
+                    }
+                    ("", _) => match after.chars().next() {
+                        Some(escaped @ ('_' | '^' | '{' | '}' | '\\')) => {
+                            push_escaped(&mut out, escaped);
+                            &after[1..]
+                        }
+                        _ => {
+                            out.push('\\');
+                            after
+                        }
+                    },
+                    _ => match SYMBOLS.iter().find(|(n, _)| *n == name) {
+                        Some((_, symbol)) => {
+                            out.push_str(symbol);
+                            tail
+                        }
+                        None => {
+                            out.push('\\');
+                            after
+                        }
+                    },
+                }
+            }
+            c => {
+                push_escaped(&mut out, c);
+                after
+            }
+        };
+    }
+    // unbalanced braces: close what is still open
+    while let Some(close) = open.pop() {
+        out.push_str(close);
+    }
+    out
+}
+
+fn push_escaped(out: &mut String, c: char) {
+    match c {
+        '&' => out.push_str("&amp;"),
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        c => out.push(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_groups() {
+        assert_eq!(to_pango("V_{out}"), "V<sub>out</sub>");
+        assert_eq!(to_pango("e^{-t_{r}}"), "e<sup>-t<sub>r</sub></sup>");
+        assert_eq!(to_pango("\\bf{x^{2}}"), "<b>x<sup>2</sup></b>");
+    }
+
+    #[test]
+    fn unbalanced_braces() {
+        // open groups are closed at the end, extra closing braces are shown
+        assert_eq!(to_pango("10^{-3"), "10<sup>-3</sup>");
+        assert_eq!(to_pango("\\it{a_{b"), "<i>a<sub>b</sub></i>");
+        assert_eq!(to_pango("a}b"), "a}b");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(to_pango("a\\_\\{b\\}"), "a_{b}");
+        assert_eq!(to_pango("\\\\mu"), "\\mu");
+        assert_eq!(to_pango("R<1k & C>1n"), "R&lt;1k &amp; C&gt;1n");
+        assert_eq!(to_pango("a_b^c"), "a_b^c");
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(to_pango("10 \\mu{}s"), "10 μ{}s");
+        assert_eq!(to_pango("\\Omega"), "Ω");
+        assert_eq!(to_pango("\\unknown x"), "\\unknown x");
+        assert_eq!(to_pango("\\bf x"), "\\bf x");
+        assert_eq!(to_pango("trailing\\"), "trailing\\");
+    }
+}
@@ -0,0 +1,217 @@
+use crate::trace::Trace;
+
+/// Region a trace must stay out of
+#[derive(Clone, Debug)]
+pub enum Mask {
+    /// the trace must stay between these polylines (sorted by x, linearly interpolated).
+    /// Outside of a limit's x range there is no constraint.
+    Limits {
+        upper: Option<Vec<(f64, f64)>>,
+        lower: Option<Vec<(f64, f64)>>,
+    },
+    /// the trace must not enter the polygon
+    Polygon(Vec<(f64, f64)>),
+}
+
+/// Outcome of checking one trace against a mask
+#[derive(Clone, Debug, Default)]
+pub struct MaskResult {
+    /// runs of consecutive violating points, as index ranges (end exclusive). An empty
+    /// range (i, i) is a segment from point i - 1 to i that enters the mask although
+    /// both points are outside of it.
+    pub violations: Vec<(usize, usize)>,
+    /// total number of violating points
+    pub violating_points: usize,
+    /// x value of the first violating point or segment
+    pub first_x: Option<f64>,
+}
+
+impl MaskResult {
+    pub fn passed(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Linear interpolation of a polyline sorted by x, None outside of its x range
+fn interpolate(line: &[(f64, f64)], x: f64) -> Option<f64> {
+    let i = line.partition_point(|(lx, _)| *lx < x);
+    if i < line.len() && line[i].0 == x {
+        return Some(line[i].1);
+    }
+    if i == 0 || i == line.len() {
+        return None;
+    }
+    let ((x0, y0), (x1, y1)) = (line[i - 1], line[i]);
+    Some(y0 + (x - x0) / (x1 - x0) * (y1 - y0))
+}
+
+/// Point where the segments a0-a1 and b0-b1 cross as (position along a0-a1 from 0 to 1,
+/// x), None if they don't or are parallel
+fn intersection(
+    (a0, a1): ((f64, f64), (f64, f64)),
+    (b0, b1): ((f64, f64), (f64, f64)),
+) -> Option<(f64, f64)> {
+    let (ax, ay) = (a1.0 - a0.0, a1.1 - a0.1);
+    let (bx, by) = (b1.0 - b0.0, b1.1 - b0.1);
+    let d = ax * by - ay * bx;
+    if d == 0.0 {
+        return None;
+    }
+    let (cx, cy) = (b0.0 - a0.0, b0.1 - a0.1);
+    let t = (cx * by - cy * bx) / d;
+    let u = (cx * ay - cy * ax) / d;
+    ((0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u)).then_some((t, a0.0 + t * ax))
+}
+
+/// Even-odd rule point in polygon test
+fn inside_polygon(polygon: &[(f64, f64)], (x, y): (f64, f64)) -> bool {
+    let mut inside = false;
+    let mut j = polygon.len().wrapping_sub(1);
+    for (i, &(xi, yi)) in polygon.iter().enumerate() {
+        let (xj, yj) = polygon[j];
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+impl Mask {
+    /// Whether a single point violates the mask (non-finite points never do)
+    pub fn violates(&self, (x, y): (f64, f64)) -> bool {
+        if !(x.is_finite() && y.is_finite()) {
+            return false;
+        }
+        match self {
+            Mask::Limits { upper, lower } => {
+                let above = upper
+                    .as_ref()
+                    .and_then(|u| interpolate(u, x))
+                    .is_some_and(|limit| y > limit);
+                let below = lower
+                    .as_ref()
+                    .and_then(|l| interpolate(l, x))
+                    .is_some_and(|limit| y < limit);
+                above || below
+            }
+            Mask::Polygon(polygon) => inside_polygon(polygon, (x, y)),
+        }
+    }
+
+    /// x where the segment from `p0` to `p1` enters the mask between its end points (the
+    /// first place when going from `p0`), None if it doesn't or a point isn't finite.
+    /// Only meaningful when neither point violates the mask.
+    pub fn crossing(&self, p0: (f64, f64), p1: (f64, f64)) -> Option<f64> {
+        if ![p0.0, p0.1, p1.0, p1.1].iter().all(|v| v.is_finite()) {
+            return None;
+        }
+        match self {
+            // the distance to a limit is linear between its vertices, so with both end
+            // points inside the limits the segment can only cross at a vertex
+            Mask::Limits { upper, lower } => {
+                let (x0, x1) = (p0.0.min(p1.0), p0.0.max(p1.0));
+                let segment_y = |x: f64| p0.1 + (x - p0.0) / (p1.0 - p0.0) * (p1.1 - p0.1);
+                // above the upper limit or below the lower one
+                let limits = [(upper, 1.0), (lower, -1.0)];
+                let vertices = limits
+                    .iter()
+                    .flat_map(|(limit, sign)| limit.iter().flatten().map(move |p| (*sign, *p)));
+                vertices
+                    .filter(|(sign, (x, y))| *x > x0 && *x < x1 && sign * (segment_y(*x) - y) > 0.0)
+                    .map(|(_, (x, _))| x)
+                    .min_by(|a, b| (a - p0.0).abs().total_cmp(&(b - p0.0).abs()))
+            }
+            // with both end points outside, crossing an edge means entering the polygon
+            Mask::Polygon(polygon) => {
+                let last = polygon.len().wrapping_sub(1);
+                polygon
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, p)| {
+                        let previous = polygon[if i == 0 { last } else { i - 1 }];
+                        intersection((p0, p1), (previous, *p))
+                    })
+                    .min_by(|a, b| a.0.total_cmp(&b.0))
+                    .map(|(_, x)| x)
+            }
+        }
+    }
+
+    /// Check all points of a trace, and the segments between passing points
+    pub fn check(&self, trace: &Trace) -> MaskResult {
+        let mut result = MaskResult::default();
+        let mut run_start = None;
+        for (i, p) in trace.values.iter().enumerate() {
+            let violates = self.violates(*p);
+            if !violates && run_start.is_none() && i > 0 {
+                if let Some(x) = self.crossing(trace.values[i - 1], *p) {
+                    result.violations.push((i, i));
+                    result.first_x.get_or_insert(x);
+                }
+            }
+            match (violates, run_start) {
+                (true, None) => {
+                    run_start = Some(i);
+                    result.first_x.get_or_insert(p.0);
+                }
+                (false, Some(start)) => {
+                    result.violations.push((start, i));
+                    run_start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(start) = run_start {
+            result.violations.push((start, trace.values.len()));
+        }
+        result.violating_points = result.violations.iter().map(|(a, b)| b - a).sum();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square() -> Mask {
+        Mask::Polygon(vec![(1.0, -1.0), (2.0, -1.0), (2.0, 1.0), (1.0, 1.0)])
+    }
+
+    #[test]
+    fn segment_through_polygon() {
+        let result = square().check(&Trace::new(vec![(0.0, 0.0), (3.0, 0.0)], "t"));
+        assert_eq!(result.violations, vec![(1, 1)]);
+        assert_eq!(result.violating_points, 0);
+        assert_eq!(result.first_x, Some(1.0));
+    }
+
+    #[test]
+    fn segment_beside_polygon() {
+        let result = square().check(&Trace::new(vec![(0.0, 0.0), (0.5, 2.0)], "t"));
+        assert!(result.passed());
+    }
+
+    #[test]
+    fn point_inside_polygon() {
+        let trace = Trace::new(vec![(0.0, 0.0), (1.5, 0.0), (3.0, 0.0)], "t");
+        let result = square().check(&trace);
+        assert_eq!(result.violations, vec![(1, 2)]);
+        assert_eq!(result.violating_points, 1);
+        assert_eq!(result.first_x, Some(1.5));
+    }
+
+    #[test]
+    fn segment_over_limit_vertex() {
+        let mask = Mask::Limits {
+            upper: Some(vec![(0.0, 1.0), (1.0, -1.0), (2.0, 1.0)]),
+            lower: Some(vec![(0.0, -2.0), (2.0, -2.0)]),
+        };
+        let result = mask.check(&Trace::new(vec![(0.0, 0.0), (2.0, 0.0)], "t"));
+        assert_eq!(result.violations, vec![(1, 1)]);
+        assert_eq!(result.first_x, Some(1.0));
+
+        let below = Trace::new(vec![(0.0, -1.5), (2.0, -1.5)], "t");
+        assert!(mask.check(&below).passed());
+    }
+}
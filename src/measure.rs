@@ -0,0 +1,392 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::annotation::{Annotation, AnnotationStyle, Shape};
+use crate::cairo_utils::format_number;
+
+/// Reference levels for edge measurements, as fractions of the base-to-top amplitude
+#[derive(Clone, Copy, Debug)]
+pub struct MeasureConfig {
+    /// lower reference level for rise/fall time (default 10%)
+    pub low: f64,
+    /// upper reference level for rise/fall time (default 90%)
+    pub high: f64,
+    /// level for period, duty cycle and crossings (default 50%)
+    pub mid: f64,
+}
+
+impl Default for MeasureConfig {
+    fn default() -> Self {
+        Self {
+            low: 0.1,
+            high: 0.9,
+            mid: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edge {
+    Rising,
+    Falling,
+}
+
+/// Measurements of a waveform. Values that can't be determined (e.g. the period of a
+/// signal with fewer than two rising edges) are None.
+#[derive(Clone, Debug)]
+pub struct Measurements {
+    pub min: f64,
+    pub max: f64,
+    pub peak_to_peak: f64,
+    pub mean: f64,
+    pub rms: f64,
+    /// settled low and high levels
+    pub base: f64,
+    pub top: f64,
+    pub rise_time: Option<f64>,
+    pub fall_time: Option<f64>,
+    /// overshoot above `top` and undershoot below `base`, in percent of the amplitude
+    pub overshoot: f64,
+    pub undershoot: f64,
+    pub period: Option<f64>,
+    pub frequency: Option<f64>,
+    /// fraction of the period spent above the mid level
+    pub duty_cycle: Option<f64>,
+    /// crossings of the mid level
+    pub crossings: Vec<(f64, Edge)>,
+    /// (start, end) of the first rising and falling edge, between the low and high levels
+    rise_span: Option<(f64, f64)>,
+    fall_span: Option<(f64, f64)>,
+}
+
+/// Snapshot of a trace to measure, can be sent to a worker thread
+#[derive(Clone, Debug)]
+pub struct MeasureJob {
+    /// index of the Axes the trace belongs to
+    pub axes: usize,
+    pub name: String,
+    /// the values of the trace, contiguous as in `Trace::values`
+    pub values: Arc<VecDeque<(f64, f64)>>,
+    pub range: Option<(f64, f64)>,
+}
+
+impl MeasureJob {
+    pub fn run(&self, config: &MeasureConfig) -> Option<Measurements> {
+        measure(self.values.as_slices().0, self.range, config)
+    }
+}
+
+/// Points with x in `range` (all points if None); `values` must be sorted by x
+fn restrict(values: &[(f64, f64)], range: Option<(f64, f64)>) -> &[(f64, f64)] {
+    match range {
+        Some((x0, x1)) => {
+            let start = values.partition_point(|(x, _)| *x < x0);
+            let end = values.partition_point(|(x, _)| *x <= x1);
+            &values[start..end.max(start)]
+        }
+        None => values,
+    }
+}
+
+/// Linearly interpolated times where the waveform crosses `level`
+pub fn crossings(values: &[(f64, f64)], level: f64) -> Vec<(f64, Edge)> {
+    values
+        .windows(2)
+        .filter_map(|w| {
+            let ((x0, y0), (x1, y1)) = (w[0], w[1]);
+            let edge = if y0 < level && y1 >= level {
+                Edge::Rising
+            } else if y0 >= level && y1 < level {
+                Edge::Falling
+            } else {
+                return None;
+            };
+            Some((x0 + (level - y0) / (y1 - y0) * (x1 - x0), edge))
+        })
+        .collect()
+}
+
+/// Base and top levels: the most common values in the lower and upper half of the
+/// range (histogram mode), like an oscilloscope does. Falls back to min/max.
+pub fn levels(values: &[(f64, f64)], min: f64, max: f64) -> (f64, f64) {
+    const BINS: usize = 100;
+    if max <= min {
+        return (min, max);
+    }
+    let mut histogram = [0usize; BINS];
+    for (_, y) in values {
+        let bin = (((y - min) / (max - min)) * BINS as f64) as usize;
+        histogram[bin.min(BINS - 1)] += 1;
+    }
+    let mode = |bins: std::ops::Range<usize>| {
+        let (bin, count) = bins.map(|b| (b, histogram[b])).max_by_key(|(_, c)| *c)?;
+        // a flat histogram has no settled level
+        (count > values.len() / BINS).then(|| min + (bin as f64 + 0.5) / BINS as f64 * (max - min))
+    };
+    (
+        mode(0..BINS / 2).unwrap_or(min),
+        mode(BINS / 2..BINS).unwrap_or(max),
+    )
+}
+
+/// Duration of the first edge of the given direction, from crossing `from` to crossing `to`
+fn edge_span(values: &[(f64, f64)], from: f64, to: f64, edge: Edge) -> Option<(f64, f64)> {
+    let end = crossings(values, to)
+        .into_iter()
+        .find(|(_, e)| *e == edge)?
+        .0;
+    // last crossing of the start level before the end level is reached
+    let start = crossings(values, from)
+        .into_iter()
+        .rev()
+        .find(|(t, e)| *e == edge && *t <= end)?
+        .0;
+    Some((start, end))
+}
+
+/// Measure a waveform sorted by x, optionally only inside an x range.
+/// Non-finite samples are ignored. Returns None if fewer than two samples remain.
+pub fn measure(
+    values: &[(f64, f64)],
+    range: Option<(f64, f64)>,
+    config: &MeasureConfig,
+) -> Option<Measurements> {
+    let values: Vec<_> = restrict(values, range)
+        .iter()
+        .copied()
+        .filter(|(x, y)| x.is_finite() && y.is_finite())
+        .collect();
+    if values.len() < 2 {
+        return None;
+    }
+
+    let (min, max) = values
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), (_, y)| {
+            (min.min(*y), max.max(*y))
+        });
+
+    // time-weighted (trapezoidal) mean and rms, samples may be non-uniform
+    let duration = values[values.len() - 1].0 - values[0].0;
+    let (integral, integral_sq) = values.windows(2).fold((0.0, 0.0), |(s, sq), w| {
+        let ((x0, y0), (x1, y1)) = (w[0], w[1]);
+        let dt = x1 - x0;
+        (
+            s + 0.5 * (y0 + y1) * dt,
+            sq + (y0 * y0 + y0 * y1 + y1 * y1) / 3.0 * dt,
+        )
+    });
+    let (mean, rms) = if duration > 0.0 {
+        (integral / duration, (integral_sq / duration).sqrt())
+    } else {
+        let n = values.len() as f64;
+        let mean = values.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let rms = (values.iter().map(|(_, y)| y * y).sum::<f64>() / n).sqrt();
+        (mean, rms)
+    };
+
+    let (base, top) = levels(&values, min, max);
+    let amplitude = top - base;
+    let level = |fraction: f64| base + fraction * amplitude;
+
+    let rise_span = edge_span(&values, level(config.low), level(config.high), Edge::Rising);
+    let fall_span = edge_span(
+        &values,
+        level(config.high),
+        level(config.low),
+        Edge::Falling,
+    );
+
+    let crossings = crossings(&values, level(config.mid));
+    let rising: Vec<_> = crossings
+        .iter()
+        .filter(|(_, e)| *e == Edge::Rising)
+        .map(|(t, _)| *t)
+        .collect();
+    let period = (rising.len() >= 2)
+        .then(|| (rising[rising.len() - 1] - rising[0]) / (rising.len() - 1) as f64);
+
+    // time from each rising crossing to the following falling crossing
+    let high_times: Vec<_> = rising
+        .iter()
+        .filter_map(|r| {
+            crossings
+                .iter()
+                .find(|(t, e)| *e == Edge::Falling && t > r)
+                .map(|(t, _)| t - r)
+        })
+        .collect();
+    let duty_cycle = period
+        .filter(|_| !high_times.is_empty())
+        .map(|p| high_times.iter().sum::<f64>() / high_times.len() as f64 / p);
+
+    let percent = |v: f64| {
+        if amplitude > 0.0 {
+            100.0 * v / amplitude
+        } else {
+            0.0
+        }
+    };
+
+    Some(Measurements {
+        min,
+        max,
+        peak_to_peak: max - min,
+        mean,
+        rms,
+        base,
+        top,
+        rise_time: rise_span.map(|(a, b)| b - a),
+        fall_time: fall_span.map(|(a, b)| b - a),
+        overshoot: percent(max - top),
+        undershoot: percent(base - min),
+        period,
+        frequency: period.filter(|p| *p > 0.0).map(|p| 1.0 / p),
+        duty_cycle,
+        crossings,
+        rise_span,
+        fall_span,
+    })
+}
+
+impl Measurements {
+    /// (name, formatted value) rows for a measurement table
+    pub fn table(&self) -> Vec<(&'static str, String)> {
+        let opt = |v: Option<f64>| v.map_or("–".to_owned(), format_number);
+        vec![
+            ("Min", format_number(self.min)),
+            ("Max", format_number(self.max)),
+            ("Pk-Pk", format_number(self.peak_to_peak)),
+            ("Mean", format_number(self.mean)),
+            ("RMS", format_number(self.rms)),
+            ("Rise", opt(self.rise_time)),
+            ("Fall", opt(self.fall_time)),
+            ("Overshoot", format!("{:.1} %", self.overshoot)),
+            ("Undershoot", format!("{:.1} %", self.undershoot)),
+            ("Period", opt(self.period)),
+            ("Frequency", opt(self.frequency)),
+            ("Duty", opt(self.duty_cycle.map(|d| 100.0 * d)) + " %"),
+            ("Crossings", self.crossings.len().to_string()),
+        ]
+    }
+
+    /// Chart annotations: base/top levels and the measured rise and fall edges
+    pub fn annotations(&self) -> Vec<Annotation> {
+        let style = AnnotationStyle {
+            color: (0.0, 0.5, 0.0),
+            line_width: 1.0,
+            dash: vec![4.0, 4.0],
+            fill_alpha: 0.15,
+        };
+        let mut annotations = vec![
+            Annotation::new(Shape::HLine(self.top))
+                .with_label("top")
+                .with_style(style.clone()),
+            Annotation::new(Shape::HLine(self.base))
+                .with_label("base")
+                .with_style(style.clone()),
+        ];
+        if let Some((a, b)) = self.rise_span {
+            annotations.push(
+                Annotation::new(Shape::XSpan(a, b))
+                    .with_label(&format!("rise {}", format_number(b - a)))
+                    .with_style(style.clone()),
+            );
+        }
+        if let Some((a, b)) = self.fall_span {
+            annotations.push(
+                Annotation::new(Shape::XSpan(a, b))
+                    .with_label(&format!("fall {}", format_number(b - a)))
+                    .with_style(style),
+            );
+        }
+        annotations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pulse train with period 10: rising from 0 to 1 in 1, high for 3, falling in 2
+    /// and low for 4, sampled every 0.01
+    fn trapezoid() -> Vec<(f64, f64)> {
+        (0..5000)
+            .map(|i| {
+                let t = i as f64 * 0.01;
+                let phase = t % 10.0;
+                let y = match phase {
+                    p if p < 1.0 => p,
+                    p if p < 4.0 => 1.0,
+                    p if p < 6.0 => 1.0 - (p - 4.0) / 2.0,
+                    _ => 0.0,
+                };
+                (t, y)
+            })
+            .collect()
+    }
+
+    fn assert_close(value: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (value - expected).abs() <= tolerance,
+            "{value} is not {expected} ± {tolerance}"
+        );
+    }
+
+    #[test]
+    fn trapezoid_edges_and_timing() {
+        let m = measure(&trapezoid(), None, &MeasureConfig::default()).unwrap();
+        // the levels are histogram bin centers, 1% of the amplitude off at most
+        assert_close(m.base, 0.0, 0.01);
+        assert_close(m.top, 1.0, 0.01);
+        assert_close(m.rise_time.unwrap(), 0.8, 0.01);
+        assert_close(m.fall_time.unwrap(), 1.6, 0.02);
+        assert_close(m.period.unwrap(), 10.0, 1e-9);
+        assert_close(m.frequency.unwrap(), 0.1, 1e-9);
+        // from the mid level crossing of the rise (0.5) to that of the fall (5)
+        assert_close(m.duty_cycle.unwrap(), 0.45, 1e-3);
+        assert_close(m.overshoot, 0.0, 1.0);
+        assert_eq!(m.crossings.len(), 10);
+    }
+
+    #[test]
+    fn overshoot_above_top_level() {
+        let mut values = trapezoid();
+        // a single sample above the plateau
+        values[200].1 = 1.25;
+        let m = measure(&values, None, &MeasureConfig::default()).unwrap();
+        assert_close(m.top, 1.0, 0.01);
+        assert_close(m.overshoot, 25.0, 1.0);
+        assert_close(m.undershoot, 0.0, 1.0);
+    }
+
+    #[test]
+    fn range_limits_the_measurement() {
+        // only the first period: one rising edge, so no period or duty cycle
+        let m = measure(&trapezoid(), Some((0.0, 9.0)), &MeasureConfig::default()).unwrap();
+        assert!(m.rise_time.is_some() && m.fall_time.is_some());
+        assert_eq!(m.period, None);
+        assert_eq!(m.duty_cycle, None);
+    }
+
+    #[test]
+    fn flat_signal_has_no_edges() {
+        let values: Vec<_> = (0..100).map(|i| (i as f64, 3.0)).collect();
+        let m = measure(&values, None, &MeasureConfig::default()).unwrap();
+        assert_eq!((m.base, m.top, m.peak_to_peak), (3.0, 3.0, 0.0));
+        assert_close(m.mean, 3.0, 1e-12);
+        assert_close(m.rms, 3.0, 1e-12);
+        assert_eq!((m.rise_time, m.fall_time, m.period), (None, None, None));
+        assert_eq!((m.overshoot, m.undershoot), (0.0, 0.0));
+        assert!(m.crossings.is_empty());
+    }
+
+    #[test]
+    fn fewer_than_two_points() {
+        let config = MeasureConfig::default();
+        assert!(measure(&[], None, &config).is_none());
+        assert!(measure(&[(0.0, 1.0)], None, &config).is_none());
+        assert!(measure(&[(0.0, 1.0), (1.0, f64::NAN)], None, &config).is_none());
+        assert!(measure(&trapezoid(), Some((2.0, 2.005)), &config).is_none());
+    }
+}
@@ -1,6 +1,22 @@
-use std::{cell::RefCell, rc::Rc};
-
 use crate::axes::{Axes, AxesCursorPosition};
+use crate::axis::{Axis, ScaleMode};
+use crate::cairo_utils::{text_aligned, Fonts, TextPos};
+use crate::colormap::Colormap;
+use crate::expr::{common_grid, interpolate, signal_names, Derived, ExprError};
+use crate::eye::{Eye, EyeConfig, EyeMeasurements};
+use crate::grid::GridTicks;
+use crate::measure::{MeasureConfig, MeasureJob, Measurements};
+use crate::render::{CachedLayer, LayerCache, Renderer};
+use crate::spectrum::{Magnitude, Spectrum, SpectrumMetrics, Window};
+use crate::stream::{Stream, StreamSender};
+use crate::theme::Theme;
+use crate::trace::{ScatterStyle, Trace, TraceKind, XOrder};
+use std::{cell::RefCell, collections::HashMap};
+
+/// Whether an Axes rectangle has any area (Axes in collapsed rows have none)
+fn is_shown(rect: &gtk::cairo::Rectangle) -> bool {
+    rect.width() > 0.0 && rect.height() > 0.0
+}
 
 #[derive(Clone, Copy, Debug)]
 pub enum PlotCursorPosition {
@@ -8,13 +24,220 @@ pub enum PlotCursorPosition {
     None,
 }
 
+/// Cell of the layout grid an Axes occupies, spanning `row_span` rows and `col_span`
+/// columns from (`row`, `col`)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GridCell {
+    pub row: usize,
+    pub col: usize,
+    pub row_span: usize,
+    pub col_span: usize,
+}
+
+impl GridCell {
+    pub fn new(row: usize, col: usize) -> Self {
+        Self {
+            row,
+            col,
+            row_span: 1,
+            col_span: 1,
+        }
+    }
+
+    pub fn span(self, row_span: usize, col_span: usize) -> Self {
+        Self {
+            row_span: row_span.max(1),
+            col_span: col_span.max(1),
+            ..self
+        }
+    }
+}
+
+/// Coordinates of the corners of an inset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InsetCoords {
+    /// data coordinates of the parent Axes
+    Data,
+    /// 0 to 1 across the chart area of the parent, from the bottom left corner
+    Relative,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Placement {
+    Grid(GridCell),
+    /// inside the chart of the Axes `parent`, between the corners (x0, y0) and (x1, y1)
+    Inset {
+        parent: usize,
+        corners: ((f64, f64), (f64, f64)),
+        coords: InsetCoords,
+    },
+}
+
+/// An Axes and its place in the Plot
+struct Entry {
+    axes: Axes,
+    placement: Placement,
+    /// Axes in the same group keep their x (y) ranges in sync (the index of the first)
+    x_group: usize,
+    y_group: usize,
+}
+
+/// Space around the figure title, in addition to its font size, in pixels
+const TITLE_PADDING: f64 = 14.0;
+/// Height of a collapsed grid row in pixels
+const COLLAPSED_HEIGHT: f64 = 8.0;
+/// Rows can't be dragged smaller than this (in pixels)
+const MIN_ROW_HEIGHT: f64 = 40.0;
+/// Distance from a row edge (in pixels) within which the splitter can be grabbed
+const SPLITTER_GRAB: f64 = 4.0;
+
+/// Owns all view state: the Axes (with their Axis and Traces) and the links between
+/// them. All changes go through `&mut self`, so the GUI only needs one shared handle to
+/// the Plot. Trace data is reference counted and immutable, so it can be handed to
+/// worker threads (see `measure_jobs`).
 pub struct Plot {
-    axes: Vec<(Rc<RefCell<Axes>>, f64)>,
+    /// figure title above all Axes (in label markup)
+    title: Option<String>,
+    entries: Vec<Entry>,
+    /// relative sizes of the grid rows and columns, 1 if not set
+    row_heights: Vec<f64>,
+    col_widths: Vec<f64>,
+    /// rows shrunk to a thin strip, indexed by row
+    collapsed: Vec<bool>,
+    /// the only row shown, if any
+    maximized: Option<usize>,
+    /// Axes in the same column (row) with the same span share their x (y) axis
+    share_x_per_column: bool,
+    share_y_per_row: bool,
+    /// traces computed from expressions, found by their (unique) trace name
+    derived: Vec<Derived>,
+    /// XY Axes and the time-domain Axes their cursor time is shown in
+    xy_links: Vec<(usize, Vec<usize>)>,
+    streams: Vec<Stream>,
+    /// renders the traces on worker threads if background rendering is on
+    renderer: Option<Renderer>,
+    /// background and trace layers from the last draw, so cursor motion only redraws
+    /// the overlay
+    cache: LayerCache,
+    /// ticks of the grid axes of each Axes in the cached background, by entry index, so
+    /// a grid above the traces is drawn at the labeled ticks
+    grid_ticks: RefCell<HashMap<usize, GridTicks>>,
+    /// device pixels per unit of the cached layers
+    scale_factor: f64,
+    /// fonts of the figure title, also given to every Axes
+    fonts: Fonts,
+    /// colors of the background and the figure title, also given to every Axes
+    theme: Theme,
 }
 
 impl Plot {
     pub fn new() -> Self {
-        Self { axes: vec![] }
+        Self {
+            title: None,
+            entries: vec![],
+            row_heights: vec![],
+            col_widths: vec![],
+            collapsed: vec![],
+            maximized: None,
+            share_x_per_column: false,
+            share_y_per_row: false,
+            derived: vec![],
+            xy_links: vec![],
+            streams: vec![],
+            renderer: None,
+            cache: LayerCache::new(),
+            grid_ticks: RefCell::default(),
+            scale_factor: 1.0,
+            fonts: Fonts::default(),
+            theme: Theme::default(),
+        }
+    }
+
+    /// Render the traces off the main thread (for large data sets). While a frame is
+    /// rendering, the previous one is shown scaled to the current view; call
+    /// `poll_renders` regularly to pick up finished frames.
+    pub fn set_background_rendering(&mut self, enabled: bool) {
+        self.renderer = enabled.then(Renderer::new);
+        self.cache.invalidate(CachedLayer::Traces);
+    }
+
+    /// Take over frames finished by background rendering, returns whether a redraw is
+    /// needed
+    pub fn poll_renders(&self) -> bool {
+        let rendered = self
+            .renderer
+            .as_ref()
+            .is_some_and(|renderer| renderer.poll());
+        if rendered {
+            self.cache.invalidate(CachedLayer::Traces);
+        }
+        rendered
+    }
+
+    /// Whether streams are open or frames are rendering in the background, so
+    /// `poll_streams` and `poll_renders` have to be called on the next frames
+    pub fn is_busy(&self) -> bool {
+        !self.streams.is_empty() || self.renderer.as_ref().is_some_and(Renderer::pending)
+    }
+
+    /// Scale factor of the widget (2 on most HiDPI displays), so the cached layers have
+    /// the resolution of the screen
+    pub fn set_scale_factor(&mut self, scale: f64) {
+        self.scale_factor = scale;
+    }
+
+    /// Set the font family and sizes of the figure title and of all Axes, including
+    /// the ones added later
+    pub fn set_fonts(&mut self, fonts: Fonts) {
+        for entry in &mut self.entries {
+            entry.axes.fonts = fonts.clone();
+        }
+        self.fonts = fonts;
+        self.invalidate();
+    }
+
+    pub fn fonts(&self) -> &Fonts {
+        &self.fonts
+    }
+
+    /// Set the colors of the Plot and of all Axes, including the ones added later
+    pub fn set_theme(&mut self, theme: Theme) {
+        for entry in &mut self.entries {
+            entry.axes.theme = theme.clone();
+        }
+        self.theme = theme;
+        self.invalidate();
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// Draw everything again on the next draw, after changes to the layout, colors,
+    /// fonts or to an Axes through `axes_mut` that show outside the overlay. Changes of
+    /// the axis ranges are picked up by the next draw anyway, and cursor and annotation
+    /// changes only affect the overlay.
+    pub fn invalidate(&self) {
+        self.cache.invalidate_all();
+    }
+
+    /// Draw the traces again on the next draw, after their data changed
+    pub fn invalidate_traces(&self) {
+        self.cache.invalidate(CachedLayer::Traces);
+        self.cache.invalidate_insets();
+    }
+
+    /// Ranges of all axes, every cached layer depends on them
+    fn axis_ranges(&self) -> Vec<Option<(f64, f64)>> {
+        let mut ranges = vec![];
+        for axes in self.entries.iter().map(|entry| &entry.axes) {
+            ranges.push(Some(axes.primary_x.range()));
+            ranges.push(Some(axes.primary_y.range()));
+            ranges.push(axes.secondary_x.as_ref().map(Axis::range));
+            ranges.push(axes.secondary_y.as_ref().map(Axis::range));
+            ranges.push(axes.colorbar.as_ref().map(|cb| cb.axis.range()));
+        }
+        ranges
     }
 
     pub fn draw(
@@ -23,32 +246,897 @@ impl Plot {
         // pixel coordinates for the full Plot area:
         rect: gtk::cairo::Rectangle,
     ) {
-        cx.set_source_rgb(1.0, 1.0, 1.0);
-        cx.paint().unwrap();
+        let rects = self.axes_rects(rect);
+        self.cache.set_ranges(self.axis_ranges());
+        // insets cover their parent, so they are drawn on top of all cached layers
+        let grid = || {
+            let entries = self.entries.iter().zip(&rects).enumerate();
+            entries.filter(|(_, (entry, r))| {
+                matches!(entry.placement, Placement::Grid(_)) && is_shown(r)
+            })
+        };
+        let scale = self.scale_factor;
+        self.cache
+            .paint(cx, rect, scale, CachedLayer::Background, |cx| {
+                let (r, g, b) = self.theme.background;
+                cx.set_source_rgb(r, g, b);
+                cx.paint().unwrap();
+                self.draw_title(cx, rect);
+                self.draw_collapsed_rows(cx, rect);
+                let mut grid_ticks = self.grid_ticks.borrow_mut();
+                grid_ticks.clear();
+                for (i, (entry, ax_rect)) in grid() {
+                    grid_ticks.insert(i, entry.axes.draw_background(cx, *ax_rect));
+                }
+            });
+        self.cache
+            .paint(cx, rect, scale, CachedLayer::Traces, |cx| {
+                let (grid_ticks, none) = (self.grid_ticks.borrow(), GridTicks::default());
+                for (i, (entry, ax_rect)) in grid() {
+                    let ticks = grid_ticks.get(&i).unwrap_or(&none);
+                    entry
+                        .axes
+                        .draw_traces(cx, *ax_rect, ticks, |cx, chart| match &self.renderer {
+                            Some(renderer) => renderer.draw_layer(cx, chart, i, &entry.axes),
+                            None => (0..entry.axes.traces.len()).for_each(|t| {
+                                entry.axes.draw_trace(cx, chart, t, &|| false);
+                            }),
+                        });
+                }
+            });
+        let shown = self.entries.iter().zip(&rects).enumerate();
+        for (i, (entry, ax_rect)) in shown.filter(|(_, (_, r))| is_shown(r)) {
+            if let Placement::Inset { .. } = entry.placement {
+                let layer = CachedLayer::Inset(i);
+                self.cache.paint(cx, *ax_rect, scale, layer, |cx| {
+                    Self::draw_inset_layers(cx, &entry.axes, *ax_rect);
+                });
+            }
+            entry.axes.draw_overlay(cx, *ax_rect);
+        }
+    }
 
-        let h_sum: f64 = self.axes.iter().map(|(_, h)| h).sum();
+    pub fn set_title(&mut self, title: Option<&str>) {
+        self.title = title.map(str::to_owned);
+        self.invalidate();
+    }
 
-        let mut y = rect.y();
-        for (ax, h) in self.axes.iter() {
-            let row_height_px = (*h / h_sum * rect.height()).round();
-            ax.borrow().draw(
-                cx,
-                gtk::cairo::Rectangle::new(rect.x(), y, rect.width(), row_height_px),
+    /// Area left for the Axes below the title
+    fn content_rect(&self, rect: gtk::cairo::Rectangle) -> gtk::cairo::Rectangle {
+        let top = self.title_height();
+        gtk::cairo::Rectangle::new(rect.x(), rect.y() + top, rect.width(), rect.height() - top)
+    }
+
+    /// Height reserved for the figure title, 0 without one
+    fn title_height(&self) -> f64 {
+        match self.title {
+            Some(_) => self.fonts.plot_title + TITLE_PADDING,
+            None => 0.0,
+        }
+    }
+
+    fn draw_title(&self, cx: &gtk::cairo::Context, rect: gtk::cairo::Rectangle) {
+        if let Some(title) = &self.title {
+            let bottom_center = (
+                rect.x() + rect.width() / 2.0,
+                rect.y() + self.title_height(),
             );
-            y += row_height_px;
+            let font = self.fonts.font(self.fonts.plot_title, true);
+            let (r, g, b) = self.theme.text;
+            cx.set_source_rgb(r, g, b);
+            text_aligned(cx, bottom_center, title, TextPos::Top, &font, 4.0, false);
+        }
+    }
+
+    /// Collapsed rows are drawn as a gray strip that can be clicked to expand them
+    fn draw_collapsed_rows(&self, cx: &gtk::cairo::Context, rect: gtk::cairo::Rectangle) {
+        if self.maximized.is_some() {
+            return;
+        }
+        let ys = self.row_edges(rect);
+        let (r, g, b) = self.theme.grid_major;
+        for row in (0..ys.len() - 1).filter(|row| self.is_row_collapsed(*row)) {
+            cx.set_source_rgb(r, g, b);
+            cx.rectangle(
+                rect.x(),
+                ys[row] + 1.0,
+                rect.width(),
+                ys[row + 1] - ys[row] - 2.0,
+            );
+            cx.fill().unwrap();
+        }
+    }
+
+    /// Draw an inset completely, on an opaque background
+    fn draw_inset(cx: &gtk::cairo::Context, axes: &Axes, rect: gtk::cairo::Rectangle) {
+        Self::draw_inset_layers(cx, axes, rect);
+        axes.draw_overlay(cx, rect);
+    }
+
+    /// Draw the background and traces of an inset, on an opaque background
+    fn draw_inset_layers(cx: &gtk::cairo::Context, axes: &Axes, rect: gtk::cairo::Rectangle) {
+        let (r, g, b) = axes.theme.background;
+        cx.set_source_rgb(r, g, b);
+        cx.rectangle(rect.x(), rect.y(), rect.width(), rect.height());
+        cx.fill().unwrap();
+        let grid_ticks = axes.draw_background(cx, rect);
+        axes.draw_traces(cx, rect, &grid_ticks, |cx, chart| {
+            for i in 0..axes.traces.len() {
+                axes.draw_trace(cx, chart, i, &|| false);
+            }
+        });
+    }
+
+    /// Number of (rows, columns) of the layout grid
+    fn grid_size(&self) -> (usize, usize) {
+        self.entries
+            .iter()
+            .filter_map(|entry| match entry.placement {
+                Placement::Grid(cell) => Some((cell.row + cell.row_span, cell.col + cell.col_span)),
+                Placement::Inset { .. } => None,
+            })
+            .fold((0, 0), |(rows, cols), (r, c)| (rows.max(r), cols.max(c)))
+    }
+
+    /// Pixel positions of the edges of cells sharing `length`. Cells have a fixed size
+    /// in pixels, or share the rest of the space by their weight (1 if not set).
+    fn edges(fixed: &[Option<f64>], weights: &[f64], start: f64, length: f64) -> Vec<f64> {
+        let weight = |i: usize| weights.get(i).copied().unwrap_or(1.0).max(0.0);
+        let fixed_total: f64 = fixed.iter().flatten().sum();
+        let flexible = (length - fixed_total).max(0.0);
+        let total: f64 = (0..fixed.len())
+            .filter(|i| fixed[*i].is_none())
+            .map(weight)
+            .sum();
+        let mut edges = vec![start];
+        let mut pos = start;
+        for (i, size) in fixed.iter().enumerate() {
+            pos += size.unwrap_or_else(|| weight(i) / total.max(f64::MIN_POSITIVE) * flexible);
+            edges.push(pos.round());
+        }
+        edges
+    }
+
+    /// Fixed heights of the grid rows: collapsed rows are a thin strip, and all rows but
+    /// a maximized one are hidden. None for rows sized by their weight.
+    fn fixed_row_heights(&self) -> Vec<Option<f64>> {
+        let (rows, _) = self.grid_size();
+        let maximized = self.maximized.filter(|row| *row < rows);
+        (0..rows)
+            .map(|row| match maximized {
+                Some(m) => (row != m).then_some(0.0),
+                None => self.is_row_collapsed(row).then_some(COLLAPSED_HEIGHT),
+            })
+            .collect()
+    }
+
+    fn row_edges(&self, rect: gtk::cairo::Rectangle) -> Vec<f64> {
+        let rect = self.content_rect(rect);
+        Self::edges(
+            &self.fixed_row_heights(),
+            &self.row_heights,
+            rect.y(),
+            rect.height(),
+        )
+    }
+
+    pub fn is_row_collapsed(&self, row: usize) -> bool {
+        self.collapsed.get(row).copied().unwrap_or(false)
+    }
+
+    /// Pixel rectangles of all Axes: grid cells sized by the row and column weights, and
+    /// insets placed inside the chart of their parent
+    fn axes_rects(&self, rect: gtk::cairo::Rectangle) -> Vec<gtk::cairo::Rectangle> {
+        let (_, cols) = self.grid_size();
+        let fixed_rows = self.fixed_row_heights();
+        let ys = self.row_edges(rect);
+        let xs = Self::edges(&vec![None; cols], &self.col_widths, rect.x(), rect.width());
+        let hidden = gtk::cairo::Rectangle::new(rect.x(), rect.y(), 0.0, 0.0);
+
+        let mut rects: Vec<gtk::cairo::Rectangle> = vec![];
+        for entry in &self.entries {
+            let r = match entry.placement {
+                // Axes only in collapsed or hidden rows aren't shown
+                Placement::Grid(cell)
+                    if fixed_rows[cell.row..cell.row + cell.row_span]
+                        .iter()
+                        .all(Option::is_some) =>
+                {
+                    hidden
+                }
+                Placement::Grid(cell) => {
+                    let (x0, x1) = (xs[cell.col], xs[cell.col + cell.col_span]);
+                    let (y0, y1) = (ys[cell.row], ys[cell.row + cell.row_span]);
+                    gtk::cairo::Rectangle::new(x0, y0, x1 - x0, y1 - y0)
+                }
+                Placement::Inset { parent, .. } if !is_shown(&rects[parent]) => hidden,
+                Placement::Inset {
+                    parent,
+                    corners,
+                    coords,
+                } => {
+                    // parents are always added before their insets
+                    let parent_axes = &self.entries[parent].axes;
+                    let chart = parent_axes.chart_rect(rects[parent]);
+                    let to_px = |(x, y): (f64, f64)| {
+                        let (x, y) = match coords {
+                            InsetCoords::Relative => (x, y),
+                            InsetCoords::Data => (
+                                parent_axes.primary_x.data_to_axis(x),
+                                parent_axes.primary_y.data_to_axis(y),
+                            ),
+                        };
+                        (
+                            chart.x() + x * chart.width(),
+                            chart.y() + (1.0 - y) * chart.height(),
+                        )
+                    };
+                    let ((ax, ay), (bx, by)) = (to_px(corners.0), to_px(corners.1));
+                    gtk::cairo::Rectangle::new(
+                        ax.min(bx),
+                        ay.min(by),
+                        (bx - ax).abs(),
+                        (by - ay).abs(),
+                    )
+                }
+            };
+            rects.push(r);
+        }
+        rects
+    }
+
+    /// Set the relative height of a grid row (1 by default)
+    pub fn set_row_height(&mut self, row: usize, weight: f64) {
+        if self.row_heights.len() <= row {
+            self.row_heights.resize(row + 1, 1.0);
+        }
+        self.row_heights[row] = weight;
+        self.invalidate();
+    }
+
+    /// Set the relative width of a grid column (1 by default)
+    pub fn set_col_width(&mut self, col: usize, weight: f64) {
+        if self.col_widths.len() <= col {
+            self.col_widths.resize(col + 1, 1.0);
+        }
+        self.col_widths[col] = weight;
+        self.invalidate();
+    }
+
+    /// Relative heights of the grid rows, e.g. to save them with the session
+    pub fn row_heights(&self) -> Vec<f64> {
+        let (rows, _) = self.grid_size();
+        (0..rows)
+            .map(|row| self.row_heights.get(row).copied().unwrap_or(1.0))
+            .collect()
+    }
+
+    /// Set the relative heights of the first rows, e.g. restored from a session. Heights
+    /// for rows that don't exist yet are used once they are added.
+    pub fn set_row_heights(&mut self, heights: &[f64]) {
+        for (row, height) in heights.iter().enumerate() {
+            self.set_row_height(row, *height);
+        }
+    }
+
+    /// Grid row at pixel height `y`
+    pub fn row_at(&self, rect: gtk::cairo::Rectangle, y: f64) -> Option<usize> {
+        let ys = self.row_edges(rect);
+        (0..ys.len().saturating_sub(1)).find(|row| (ys[*row]..ys[row + 1]).contains(&y))
+    }
+
+    /// Splitter at pixel position (x, y): the index of the row above an edge between
+    /// two rows that can be resized
+    pub fn splitter_at(&self, rect: gtk::cairo::Rectangle, x: f64, y: f64) -> Option<usize> {
+        if !(rect.x()..rect.x() + rect.width()).contains(&x) {
+            return None;
+        }
+        let fixed = self.fixed_row_heights();
+        let ys = self.row_edges(rect);
+        (0..fixed.len().saturating_sub(1)).find(|row| {
+            fixed[*row].is_none()
+                && fixed[row + 1].is_none()
+                && (ys[row + 1] - y).abs() <= SPLITTER_GRAB
+        })
+    }
+
+    /// Move the splitter below `row` (see `splitter_at`) by `dy` pixels, moving height
+    /// between the rows on both sides
+    pub fn drag_splitter(&mut self, rect: gtk::cairo::Rectangle, row: usize, dy: f64) {
+        let ys = self.row_edges(rect);
+        if row + 2 >= ys.len() {
+            return;
+        }
+        let (above, below) = (ys[row + 1] - ys[row], ys[row + 2] - ys[row + 1]);
+        let total = above + below;
+        if total < 2.0 * MIN_ROW_HEIGHT {
+            return;
+        }
+        let above = (above + dy).clamp(MIN_ROW_HEIGHT, total - MIN_ROW_HEIGHT);
+        let heights = self.row_heights();
+        let weight = heights[row] + heights[row + 1];
+        self.set_row_height(row, weight * above / total);
+        self.set_row_height(row + 1, weight * (total - above) / total);
+    }
+
+    /// Collapse a grid row to a thin strip, or expand it again
+    pub fn toggle_collapsed(&mut self, row: usize) {
+        if self.collapsed.len() <= row {
+            self.collapsed.resize(row + 1, false);
+        }
+        self.collapsed[row] = !self.collapsed[row];
+        self.invalidate();
+    }
+
+    /// Show only the given grid row, or all rows again if it is already maximized
+    pub fn toggle_maximized(&mut self, row: usize) {
+        self.maximized = match self.maximized {
+            Some(m) if m == row => None,
+            _ => Some(row),
+        };
+        self.invalidate();
+    }
+
+    /// Link the x axes of all Axes in the same grid column (with the same column span)
+    pub fn set_share_x_per_column(&mut self, shared: bool) {
+        self.share_x_per_column = shared;
+    }
+
+    /// Link the y axes of all Axes in the same grid row (with the same row span)
+    pub fn set_share_y_per_row(&mut self, shared: bool) {
+        self.share_y_per_row = shared;
+    }
+
+    /// Draw everything straight to the target of `cx`, without the cached layers and
+    /// background rendering (for exports)
+    fn draw_uncached(&self, cx: &gtk::cairo::Context, rect: gtk::cairo::Rectangle) {
+        let (r, g, b) = self.theme.background;
+        cx.set_source_rgb(r, g, b);
+        cx.paint().unwrap();
+        self.draw_title(cx, rect);
+        let rects = self.axes_rects(rect);
+        for (entry, ax_rect) in self.entries.iter().zip(rects).filter(|(_, r)| is_shown(r)) {
+            match entry.placement {
+                Placement::Grid(_) => entry.axes.draw(cx, ax_rect),
+                Placement::Inset { .. } => Self::draw_inset(cx, &entry.axes, ax_rect),
+            }
         }
     }
 
     fn export_svg(&self) {
         let svg = gtk::cairo::SvgSurface::new(800.0, 500.0, Some("abc.svg")).unwrap();
-        let mut cx = gtk::cairo::Context::new(svg).unwrap();
-        cx.set_source_rgb(1.0, 1.0, 1.0);
-        cx.paint().unwrap();
-        self.draw(&mut cx, gtk::cairo::Rectangle::new(0.0, 0.0, 800.0, 500.0));
+        let cx = gtk::cairo::Context::new(svg).unwrap();
+        self.draw_uncached(&cx, gtk::cairo::Rectangle::new(0.0, 0.0, 800.0, 500.0));
+    }
+
+    /// Write a PNG of the Plot laid out at `size` (in pixels at 96 DPI) and rendered at
+    /// `dpi`, e.g. 300 for print. Text, lines and images are drawn at the full
+    /// resolution rather than scaled up.
+    pub fn export_png(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        size: (f64, f64),
+        dpi: f64,
+    ) -> Result<(), gtk::cairo::IoError> {
+        let scale = dpi / 96.0;
+        let pixels = (
+            (size.0 * scale).ceil() as i32,
+            (size.1 * scale).ceil() as i32,
+        );
+        let format = gtk::cairo::Format::ARgb32;
+        let surface = gtk::cairo::ImageSurface::create(format, pixels.0, pixels.1)?;
+        surface.set_device_scale(scale, scale);
+        {
+            let cx = gtk::cairo::Context::new(&surface)?;
+            self.draw_uncached(&cx, gtk::cairo::Rectangle::new(0.0, 0.0, size.0, size.1));
+        }
+        surface.write_to_png(&mut std::fs::File::create(path)?)
+    }
+
+    /// Add an Axes in a new row below the existing ones (in the first column), returns
+    /// its index
+    pub fn add_axes(&mut self, axes: Axes) -> usize {
+        let row = self.grid_size().0;
+        self.add_axes_at(axes, GridCell::new(row, 0))
+    }
+
+    /// Add an Axes in a cell of the layout grid, returns its index. The grid grows to
+    /// fit the cell.
+    pub fn add_axes_at(&mut self, axes: Axes, cell: GridCell) -> usize {
+        self.push(
+            axes,
+            Placement::Grid(cell.span(cell.row_span, cell.col_span)),
+        )
+    }
+
+    /// Add an Axes inside the chart of the Axes `parent`, between the corners `from` and
+    /// `to` (in data or relative coordinates of the parent). Returns its index, or None
+    /// if there is no Axes `parent`.
+    pub fn add_inset(
+        &mut self,
+        axes: Axes,
+        parent: usize,
+        from: (f64, f64),
+        to: (f64, f64),
+        coords: InsetCoords,
+    ) -> Option<usize> {
+        if parent >= self.entries.len() {
+            return None;
+        }
+        let placement = Placement::Inset {
+            parent,
+            corners: (from, to),
+            coords,
+        };
+        Some(self.push(axes, placement))
+    }
+
+    fn push(&mut self, mut axes: Axes, placement: Placement) -> usize {
+        self.invalidate();
+        axes.fonts = self.fonts.clone();
+        axes.theme = self.theme.clone();
+        let index = self.entries.len();
+        self.entries.push(Entry {
+            axes,
+            placement,
+            x_group: index,
+            y_group: index,
+        });
+        index
+    }
+
+    /// Add an Axes whose x range follows the Axes at `with` (and vice versa)
+    pub fn add_axes_shared_x(&mut self, mut axes: Axes, with: usize) -> usize {
+        let Some(group) = self.entries.get(with).map(|entry| entry.x_group) else {
+            return self.add_axes(axes);
+        };
+        axes.primary_x
+            .set_range(self.entries[with].axes.primary_x.range());
+        let index = self.add_axes(axes);
+        self.entries[index].x_group = group;
+        index
     }
 
-    pub fn add_axes(&mut self, ax: Rc<RefCell<Axes>>) {
-        self.axes.push((ax, 1.0));
+    pub fn axes_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn axes(&self, index: usize) -> Option<&Axes> {
+        self.entries.get(index).map(|entry| &entry.axes)
+    }
+
+    /// Mutable access to an Axes. Call `sync_x` after changing its x range, and
+    /// `update_derived` and `invalidate_traces` after changing trace data (or use
+    /// `set_trace_values`). Other changes that aren't part of the overlay need
+    /// `invalidate`.
+    pub fn axes_mut(&mut self, index: usize) -> Option<&mut Axes> {
+        self.entries.get_mut(index).map(|entry| &mut entry.axes)
+    }
+
+    /// Whether the Axes `a` and `b` share their x axis
+    fn shares_x(&self, a: usize, b: usize) -> bool {
+        let (a, b) = (&self.entries[a], &self.entries[b]);
+        let same_column = match (a.placement, b.placement) {
+            (Placement::Grid(ca), Placement::Grid(cb)) => {
+                (ca.col, ca.col_span) == (cb.col, cb.col_span)
+            }
+            _ => false,
+        };
+        a.x_group == b.x_group || (self.share_x_per_column && same_column)
+    }
+
+    /// Whether the Axes `a` and `b` share their y axis
+    fn shares_y(&self, a: usize, b: usize) -> bool {
+        let (a, b) = (&self.entries[a], &self.entries[b]);
+        let same_row = match (a.placement, b.placement) {
+            (Placement::Grid(ca), Placement::Grid(cb)) => {
+                (ca.row, ca.row_span) == (cb.row, cb.row_span)
+            }
+            _ => false,
+        };
+        a.y_group == b.y_group || (self.share_y_per_row && same_row)
+    }
+
+    /// Copy the x range of the Axes at `from` to all Axes sharing its x axis, except
+    /// locked ones
+    pub fn sync_x(&mut self, from: usize) {
+        let Some(entry) = self.entries.get(from) else {
+            return;
+        };
+        let range = entry.axes.primary_x.range();
+        for i in 0..self.entries.len() {
+            if i != from && self.shares_x(i, from) {
+                let axes = &mut self.entries[i].axes;
+                if axes.primary_x.scale_mode != ScaleMode::Locked {
+                    axes.primary_x.set_range(range);
+                    axes.autoscale_visible();
+                }
+            }
+        }
+    }
+
+    /// Copy the y range of the Axes at `from` to all Axes sharing its y axis, except
+    /// locked ones
+    pub fn sync_y(&mut self, from: usize) {
+        let Some(entry) = self.entries.get(from) else {
+            return;
+        };
+        let range = entry.axes.primary_y.range();
+        for i in 0..self.entries.len() {
+            if i != from && self.shares_y(i, from) {
+                let axis = &mut self.entries[i].axes.primary_y;
+                if axis.scale_mode != ScaleMode::Locked {
+                    axis.set_range(range);
+                }
+            }
+        }
+    }
+
+    /// (Axes index, trace index) of the first trace called `name`
+    fn find_trace(&self, name: &str) -> Option<(usize, usize)> {
+        self.entries.iter().enumerate().find_map(|(i, entry)| {
+            let trace = entry.axes.traces.iter().position(|t| t.name == name)?;
+            Some((i, trace))
+        })
+    }
+
+    /// The first trace called `name`, in any Axes
+    fn trace_named(&self, name: &str) -> Option<&Trace> {
+        let (index, trace) = self.find_trace(name)?;
+        Some(&self.entries[index].axes.traces[trace])
+    }
+
+    /// Values of the first trace called `name`, for evaluating expressions
+    fn trace_values(&self, name: &str) -> Option<&[(f64, f64)]> {
+        self.trace_named(name).map(Trace::points)
+    }
+
+    /// Revisions of the traces a derived trace is computed from, None for missing ones
+    fn source_revisions(&self, derived: &Derived) -> Vec<Option<u64>> {
+        let signals = derived.expr.signals();
+        let source = |name: &String| signal_names(name).find_map(|n| self.trace_named(n));
+        signals
+            .iter()
+            .map(|name| source(name).map(Trace::revision))
+            .collect()
+    }
+
+    /// Replace the data of a trace, rescale and update the traces derived from it
+    pub fn set_trace_values(&mut self, index: usize, trace: usize, values: Vec<(f64, f64)>) {
+        let Some(ax) = self.entries.get_mut(index).map(|entry| &mut entry.axes) else {
+            return;
+        };
+        let Some(t) = ax.traces.get_mut(trace) else {
+            return;
+        };
+        t.set_values(values);
+        ax.autoscale();
+        self.invalidate_traces();
+        // derived traces keep their old values on errors
+        let _ = self.update_derived();
+    }
+
+    /// Add a trace computed from an expression over the traces of this Plot (e.g.
+    /// `V(out) - V(in)`) to the Axes at `index`. It is named after the expression
+    /// unless a name is given; the name must not be taken by another trace.
+    pub fn add_derived(
+        &mut self,
+        index: usize,
+        expression: &str,
+        name: Option<&str>,
+    ) -> Result<(), ExprError> {
+        let mut derived = Derived::new(expression, name)?;
+        if self.find_trace(&derived.name).is_some() {
+            return Err(ExprError {
+                message: format!("there already is a trace called '{}'", derived.name),
+                position: None,
+            });
+        }
+        let trace = derived.trace(&|name| self.trace_values(name))?;
+        derived.revisions = self.source_revisions(&derived);
+        let Some(ax) = self.axes_mut(index) else {
+            return Err(ExprError {
+                message: format!("no Axes with index {index}"),
+                position: None,
+            });
+        };
+        ax.add_trace(trace);
+        self.derived.push(derived);
+        self.invalidate_traces();
+        Ok(())
+    }
+
+    /// Re-evaluate the derived traces whose sources changed. Traces that fail to
+    /// evaluate keep their previous values; the first error is returned. Derived traces
+    /// whose trace was removed are skipped.
+    pub fn update_derived(&mut self) -> Result<(), ExprError> {
+        let mut result = Ok(());
+        // in order, so derived traces can depend on earlier ones (whose revision
+        // changes when they are re-evaluated)
+        for i in 0..self.derived.len() {
+            let Some((index, trace)) = self.find_trace(&self.derived[i].name) else {
+                continue;
+            };
+            let revisions = self.source_revisions(&self.derived[i]);
+            if revisions == self.derived[i].revisions {
+                continue;
+            }
+            // a failed evaluation is retried once the sources change again
+            self.derived[i].revisions = revisions;
+            match self.derived[i]
+                .expr
+                .evaluate(&|name| self.trace_values(name))
+            {
+                Ok(values) => {
+                    self.invalidate_traces();
+                    let ax = &mut self.entries[index].axes;
+                    ax.traces[trace].set_values(values);
+                    ax.autoscale();
+                }
+                Err(e) => {
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Compute the spectrum of a trace, given as (Axes index, trace index), and show
+    /// magnitude and phase in two new Axes below the existing ones. With `visible_only`
+    /// only the visible x range is transformed. Returns the single-tone metrics, None
+    /// (and nothing is added) for a trace that isn't sorted by x.
+    pub fn add_spectrum(
+        &mut self,
+        source: (usize, usize),
+        window: Window,
+        scale: Magnitude,
+        visible_only: bool,
+    ) -> Option<SpectrumMetrics> {
+        let ax = self.axes(source.0)?;
+        let trace = ax.traces.get(source.1)?;
+        let range = visible_only.then(|| ax.primary_x.range());
+        let spectrum = Spectrum::new(trace, range, window)?;
+        let name = &trace.name;
+
+        let mut magnitude_axes = Axes::semilogx();
+        let magnitude_label = match scale {
+            Magnitude::Linear => "Magnitude",
+            Magnitude::Db => "Magnitude (dB)",
+        };
+        magnitude_axes.primary_y.set_label(Some(magnitude_label));
+        magnitude_axes.add_trace(spectrum.magnitude_trace(scale, &format!("|{name}|")));
+        magnitude_axes.zoom_fit();
+
+        let mut phase_axes = Axes::semilogx();
+        phase_axes.primary_y.set_label(Some("Phase (°)"));
+        phase_axes.primary_x.set_label(Some("Frequency (Hz)"));
+        phase_axes.add_trace(spectrum.phase_trace(&format!("∠{name}")));
+        phase_axes.zoom_fit();
+
+        let index = self.add_axes(magnitude_axes);
+        self.add_axes_shared_x(phase_axes, index);
+        spectrum.metrics(5)
+    }
+
+    /// Fold a serial data trace, given as (Axes index, trace index), into an eye diagram
+    /// shown as a density image in a new Axes. Returns the eye measurements, None (and
+    /// nothing is added) if the trace can't be folded, see `Eye::new`.
+    pub fn add_eye(
+        &mut self,
+        source: (usize, usize),
+        config: EyeConfig,
+    ) -> Option<EyeMeasurements> {
+        let trace = self.axes(source.0)?.traces.get(source.1)?;
+        let eye = Eye::new(trace, config)?;
+
+        let mut axes = Axes::linear();
+        axes.primary_x.set_label(Some("Time"));
+        axes.add_image(eye.image(&format!("{} eye", trace.name)));
+        axes.measurement_annotations = eye.measurements.annotations();
+        axes.zoom_fit();
+        self.add_axes(axes);
+        Some(eye.measurements)
+    }
+
+    /// Plot trace `y` against trace `x` (both given as (Axes index, trace index)) in a
+    /// new Axes, on the common time base of the two. The time of each point is kept in
+    /// a "time" channel, optionally shown as marker color. Moving the cursor in the XY
+    /// Axes shows the snapped time in the Axes of both sources. Returns the index of
+    /// the new Axes.
+    pub fn add_xy(
+        &mut self,
+        x: (usize, usize),
+        y: (usize, usize),
+        color_by_time: bool,
+    ) -> Option<usize> {
+        let x_trace = self.axes(x.0)?.traces.get(x.1)?;
+        let y_trace = self.axes(y.0)?.traces.get(y.1)?;
+        if x_trace.order != XOrder::Sorted || y_trace.order != XOrder::Sorted {
+            return None;
+        }
+        let times = common_grid(&[x_trace.points(), y_trace.points()]);
+        let values = times
+            .iter()
+            .map(|t| {
+                (
+                    interpolate(x_trace.points(), *t),
+                    interpolate(y_trace.points(), *t),
+                )
+            })
+            .collect();
+        let name = format!("{} vs {}", y_trace.name, x_trace.name);
+        let mut trace = Trace::parametric(values, &name)
+            .with_channel("time", times)
+            .expect("one time per point");
+        if color_by_time {
+            trace = trace.with_kind(TraceKind::Scatter(ScatterStyle {
+                size: 3.0,
+                color_by: Some(("time".to_owned(), Colormap::Viridis)),
+                ..ScatterStyle::default()
+            }));
+        }
+
+        let mut axes = Axes::linear();
+        axes.primary_x.set_label(Some(&x_trace.name));
+        axes.primary_y.set_label(Some(&y_trace.name));
+        axes.add_trace(trace);
+        axes.zoom_fit();
+        let index = self.add_axes(axes);
+        let mut linked = vec![x.0, y.0];
+        linked.dedup();
+        self.xy_links.push((index, linked));
+        Some(index)
+    }
+
+    /// Snap the cursor of the Axes under the mouse to the nearest point and image cell,
+    /// and clear it everywhere else. Time cursors of linked Axes follow XY cursors.
+    pub fn set_cursor(&mut self, position: PlotCursorPosition) {
+        for (i, entry) in self.entries.iter_mut().enumerate() {
+            let ax = &mut entry.axes;
+            match position {
+                PlotCursorPosition::Axes(index, axes_pos) if index == i => {
+                    ax.cursor = ax.snap_cursor(axes_pos);
+                    ax.image_cursor = ax.image_value_at(axes_pos);
+                }
+                _ => {
+                    ax.cursor = None;
+                    ax.image_cursor = None;
+                }
+            }
+            ax.time_cursor = None;
+        }
+        for (xy, linked) in &self.xy_links {
+            let time = self.entries[*xy].axes.cursor.as_ref().and_then(|snap| {
+                snap.channels
+                    .iter()
+                    .find(|(name, _)| name == "time")
+                    .map(|(_, t)| *t)
+            });
+            if time.is_some() {
+                for i in linked {
+                    self.entries[*i].axes.time_cursor = time;
+                }
+            }
+        }
+    }
+
+    /// Add a streaming trace holding the newest `capacity` samples to the Axes at
+    /// `index`. Samples sent through the returned sender are appended on the next
+    /// `poll_streams`.
+    pub fn add_stream(
+        &mut self,
+        index: usize,
+        name: &str,
+        capacity: usize,
+    ) -> Option<StreamSender> {
+        let ax = self.axes_mut(index)?;
+        ax.add_trace(Trace::streaming(name, capacity));
+        let (stream, sender) = Stream::new((index, ax.traces.len() - 1));
+        self.streams.push(stream);
+        Some(sender)
+    }
+
+    /// Append the samples received by all streams, rescale (and scroll axes in follow
+    /// mode) and update derived traces. Returns whether there was new data. A stream
+    /// whose trace was given channels or errors can't append to it and is closed, so
+    /// its senders fail.
+    pub fn poll_streams(&mut self) -> bool {
+        let mut changed = vec![];
+        for stream in &mut self.streams {
+            let samples = stream.poll();
+            if samples.is_empty() {
+                continue;
+            }
+            let (index, trace) = stream.target;
+            if self.entries[index].axes.traces[trace]
+                .append(&samples)
+                .is_err()
+            {
+                stream.close();
+                continue;
+            }
+            if !changed.contains(&index) {
+                changed.push(index);
+            }
+        }
+        self.streams.retain(|stream| !stream.is_closed());
+        if changed.is_empty() {
+            return false;
+        }
+        self.invalidate_traces();
+        for index in changed {
+            self.entries[index].axes.autoscale();
+            // other Axes may share a scrolling x axis
+            self.sync_x(index);
+        }
+        // derived traces may depend on the streams; keep the old values on errors
+        let _ = self.update_derived();
+        true
+    }
+
+    pub fn zoom_fit(&mut self) {
+        for entry in &mut self.entries {
+            entry.axes.zoom_fit();
+        }
+        // Axes sharing an axis show the union of their data. Locked axes and Axes
+        // without data neither add to the union nor take it.
+        let count = self.entries.len();
+        let fitted =
+            |axis: &Axis, axes: &Axes| axis.scale_mode != ScaleMode::Locked && axes.has_data();
+        let x_ranges: Vec<_> = self
+            .entries
+            .iter()
+            .map(|e| fitted(&e.axes.primary_x, &e.axes).then(|| e.axes.primary_x.range()))
+            .collect();
+        let y_ranges: Vec<_> = self
+            .entries
+            .iter()
+            .map(|e| fitted(&e.axes.primary_y, &e.axes).then(|| e.axes.primary_y.range()))
+            .collect();
+        let union = |ranges: &[Option<(f64, f64)>], shared: &dyn Fn(usize) -> bool| {
+            (0..count)
+                .filter(|j| shared(*j))
+                .filter_map(|j| ranges[j])
+                .reduce(|(a0, a1), (b0, b1)| (a0.min(b0), a1.max(b1)))
+        };
+        for i in 0..count {
+            let x = union(&x_ranges, &|j| self.shares_x(i, j));
+            let y = union(&y_ranges, &|j| self.shares_y(i, j));
+            let axes = &mut self.entries[i].axes;
+            if let (Some(x), Some(_)) = (x, x_ranges[i]) {
+                axes.primary_x.set_range(x);
+            }
+            if let (Some(y), Some(_)) = (y, y_ranges[i]) {
+                axes.primary_y.set_range(y);
+            }
+            // the x range may have grown
+            axes.autoscale_visible();
+        }
+    }
+
+    /// Fit the y axis of each Axes to its data in the visible x range
+    pub fn zoom_fit_y(&mut self) {
+        for entry in &mut self.entries {
+            entry.axes.zoom_fit_y();
+        }
+    }
+
+    pub fn zoom_at(&mut self, index: usize, position: AxesCursorPosition, scale: f64) {
+        if let Some(ax) = self.axes_mut(index) {
+            ax.zoom_at(position, scale);
+            self.sync_x(index);
+            self.sync_y(index);
+        }
+    }
+
+    pub fn pan(&mut self, index: usize, position: AxesCursorPosition, dx: f64, dy: f64) {
+        if let Some(ax) = self.axes_mut(index) {
+            ax.pan(position, dx, dy);
+            self.sync_x(index);
+            self.sync_y(index);
+        }
     }
 
     pub fn cursor_position(
@@ -57,40 +1145,122 @@ impl Plot {
         x: f64,
         y: f64,
     ) -> PlotCursorPosition {
-        if x >= rect.x() && x <= rect.x() + rect.width() {
-            let h_sum: f64 = self.axes.iter().map(|(_, h)| h).sum();
-
-            let mut current_y = rect.y();
-            for (i, (ax, h)) in self.axes.iter().enumerate() {
-                let row_height_px = (*h / h_sum * rect.height()).round();
-
-                if y >= current_y && y < current_y + row_height_px {
-                    return PlotCursorPosition::Axes(
-                        i,
-                        ax.borrow().cursor_position(
-                            gtk::cairo::Rectangle::new(
-                                rect.x(),
-                                current_y,
-                                rect.width(),
-                                row_height_px,
-                            ),
-                            x,
-                            y,
-                        ),
-                    );
+        // insets come after (and are drawn above) their parents, so search backwards
+        let rects = self.axes_rects(rect);
+        let hit = (0..self.entries.len()).rev().find(|i| {
+            let r = rects[*i];
+            (r.x()..r.x() + r.width()).contains(&x) && (r.y()..r.y() + r.height()).contains(&y)
+        });
+        match hit {
+            Some(i) => {
+                let position = self.entries[i].axes.cursor_position(rects[i], x, y);
+                PlotCursorPosition::Axes(i, position)
+            }
+            None => PlotCursorPosition::None,
+        }
+    }
+
+    /// Show the A and B markers on every Axes, at a third and two thirds of the visible
+    /// x range, or remove them if they are shown
+    pub fn toggle_markers(&mut self) {
+        for entry in &mut self.entries {
+            let axes = &mut entry.axes;
+            let markers = match axes.marker_range() {
+                Some(_) => None,
+                None => {
+                    let x = |v: f64| axes.primary_x.axis_to_data(v);
+                    Some((x(1.0 / 3.0), x(2.0 / 3.0)))
                 }
+            };
+            axes.set_markers(markers);
+        }
+    }
 
-                current_y += row_height_px;
-            }
-            PlotCursorPosition::None
-        } else {
-            PlotCursorPosition::None
+    /// Snapshots of every time-ordered trace and the x range to measure (between the A
+    /// and B markers of its Axes, or the visible range), to be measured here or on a
+    /// worker thread
+    pub fn measure_jobs(&self) -> Vec<MeasureJob> {
+        let mut jobs = vec![];
+        for (i, entry) in self.entries.iter().enumerate() {
+            let axes = &entry.axes;
+            let range = axes
+                .marker_range()
+                .unwrap_or_else(|| axes.primary_x.range());
+            // measurements need time-ordered data
+            let sorted = entry
+                .axes
+                .traces
+                .iter()
+                .filter(|t| t.order == XOrder::Sorted);
+            jobs.extend(sorted.map(|t| MeasureJob {
+                axes: i,
+                name: t.name.clone(),
+                values: t.values.clone(),
+                range: Some(range),
+            }));
+        }
+        jobs
+    }
+
+    /// Annotate the first measured trace of each Axes on the chart
+    pub fn show_measurements(&mut self, results: &[(usize, String, Measurements)]) {
+        for (i, entry) in self.entries.iter_mut().enumerate() {
+            entry.axes.measurement_annotations = results
+                .iter()
+                .find(|(axes, _, _)| *axes == i)
+                .map(|(_, _, m)| m.annotations())
+                .unwrap_or_default();
+        }
+    }
+
+    /// Measure every trace between the markers or within the visible x range and
+    /// annotate the results
+    pub fn measure(&mut self, config: &MeasureConfig) -> Vec<(String, Measurements)> {
+        let results: Vec<_> = self
+            .measure_jobs()
+            .iter()
+            .filter_map(|job| Some((job.axes, job.name.clone(), job.run(config)?)))
+            .collect();
+        self.show_measurements(&results);
+        results.into_iter().map(|(_, name, m)| (name, m)).collect()
+    }
+
+    /// (Axes index, annotation index) of the annotation at pixel position (x, y)
+    pub fn annotation_at(
+        &self,
+        rect: gtk::cairo::Rectangle,
+        x: f64,
+        y: f64,
+    ) -> Option<(usize, usize)> {
+        self.entries
+            .iter()
+            .zip(self.axes_rects(rect))
+            .enumerate()
+            // insets first, they are drawn on top
+            .rev()
+            .filter(|(_, (_, r))| is_shown(r))
+            .find_map(|(i, (entry, r))| entry.axes.annotation_at(r, x, y).map(|a| (i, a)))
+    }
+
+    /// Move an annotation (as returned by `annotation_at`) by a pixel distance
+    pub fn drag_annotation(
+        &mut self,
+        rect: gtk::cairo::Rectangle,
+        (axes, annotation): (usize, usize),
+        dx: f64,
+        dy: f64,
+    ) {
+        let rects = self.axes_rects(rect);
+        // annotations are in the overlay, the cached layers stay valid
+        if let (Some(entry), Some(r)) = (self.entries.get_mut(axes), rects.get(axes)) {
+            entry.axes.drag_annotation(*r, annotation, dx, dy);
         }
     }
 }
 
 pub mod demo {
-    use crate::axes::Trace;
+    use crate::grid::GridLayer;
+    use crate::trace::Trace;
 
     use super::*;
     use gtk::{cairo::Rectangle, prelude::*};
@@ -113,6 +1283,34 @@ pub mod demo {
         ));
     }
 
+    /// Pulse train through an underdamped second order system, to have something to measure
+    pub fn example_pulses() -> Trace {
+        let dt = 0.002;
+        let (omega, zeta) = (40.0, 0.4);
+        let (mut y, mut v) = (0.0, 0.0);
+        let values = (0..5000)
+            .map(|i| {
+                let t = i as f64 * dt;
+                let target = if t % 2.5 < 0.8 { 1.0 } else { 0.0 };
+                v += (omega * omega * (target - y) - 2.0 * zeta * omega * v) * dt;
+                y += v * dt;
+                (t, y)
+            })
+            .collect();
+        Trace::new(values, "Pulse")
+    }
+
+    /// Axes with the example pulses, for the plot widget
+    pub fn pulse_axes() -> Axes {
+        let mut axes = Axes::linear();
+        axes.set_title(Some("Pulse response, \\zeta = 0.4"));
+        axes.primary_x.set_label(Some("Time (s)"));
+        axes.primary_y.set_label(Some("V_{out} (V)"));
+        axes.add_trace(example_pulses());
+        axes.zoom_fit();
+        axes
+    }
+
     fn example2(axes: &mut Axes) {
         let xs: Vec<_> = (1_i32..=500).map(|x| x as f64 * 0.01 * PI).collect();
         let signal_a: Vec<_> = xs.iter().map(|x| 1.0 + (2.0 * x).sin()).collect();
@@ -120,17 +1318,20 @@ pub mod demo {
 
         axes.add_trace(Trace::new(
             std::iter::zip(xs.clone(), signal_a).collect(),
-            "Signal",
+            "Signal A",
         ));
         axes.add_trace(Trace::new(
             std::iter::zip(xs.clone(), signal_b).collect(),
-            "Signal",
+            "Signal B",
         ));
     }
 
     pub fn build_ui(app: &gtk::Application) {
         let darea = Rc::new(RefCell::new(
-            gtk::DrawingArea::builder().content_height(500).content_width(800).build(),
+            gtk::DrawingArea::builder()
+                .content_height(500)
+                .content_width(800)
+                .build(),
         ));
 
         struct SharedState {
@@ -140,6 +1341,8 @@ pub mod demo {
             /// cursor position from last motion event
             cursor: PlotCursorPosition,
             marker: PlotCursorPosition,
+            /// annotation being dragged, and the drag offset at the last update
+            dragging: Option<((usize, usize), (f64, f64))>,
         }
 
         let state = Rc::new(RefCell::new(SharedState {
@@ -147,12 +1350,14 @@ pub mod demo {
             current_rect: Rectangle::new(0.0, 0.0, 1.0, 1.0),
             cursor: PlotCursorPosition::None,
             marker: PlotCursorPosition::None,
+            dragging: None,
         }));
 
         let st = state.clone();
         let position = gtk::cairo::Rectangle::new(0.0, 0.0, 1.0, 1.0);
-        darea.borrow().set_draw_func(move |_da, cx, width, height| {
-            cx.set_source_rgb(1.0, 0.9, 1.0);
+        darea.borrow().set_draw_func(move |da, cx, width, height| {
+            let (r, g, b) = st.borrow().plot.theme().background;
+            cx.set_source_rgb(r, g, b);
             cx.paint().unwrap();
 
             st.borrow_mut().current_rect = Rectangle::new(
@@ -163,16 +1368,42 @@ pub mod demo {
             );
 
             let rect = st.borrow().current_rect;
+            st.borrow_mut()
+                .plot
+                .set_scale_factor(da.scale_factor() as f64);
             st.borrow_mut().plot.draw(cx, rect);
         });
 
-        let axes1 = Axes::linear(None);
-        state.borrow_mut().plot.add_axes(axes1.clone());
-        example1(&mut axes1.borrow_mut());
+        state
+            .borrow_mut()
+            .plot
+            .set_title(Some("Example signals, \\it{V}_{peak} = 1"));
 
-        let axes2 = Axes::linear(Some(axes1.borrow().primary_x.clone()));
-        state.borrow_mut().plot.add_axes(axes2.clone());
-        example2(&mut axes2.borrow_mut());
+        let mut axes1 = Axes::linear();
+        example1(&mut axes1);
+        axes1.set_title(Some("sin(\\omega t), \\omega = 1 s^{-1}"));
+        let index = state.borrow_mut().plot.add_axes(axes1);
+
+        let mut axes2 = Axes::linear();
+        example2(&mut axes2);
+        // dashed major lines on top of the traces, no vertical minor lines
+        axes2.grid.major.dash = vec![4.0, 3.0];
+        axes2.grid.minor.x = false;
+        axes2.grid.layer = GridLayer::Above;
+        state.borrow_mut().plot.add_axes_shared_x(axes2, index);
+
+        // Lissajous figure of the two signals in example2
+        state.borrow_mut().plot.add_xy((1, 0), (1, 1), true);
+
+        // the first signal again, small in the top right corner of its Axes
+        let mut detail = Axes::linear();
+        example1(&mut detail);
+        let corners = ((0.6, 0.55), (0.98, 0.98));
+        let relative = InsetCoords::Relative;
+        state
+            .borrow_mut()
+            .plot
+            .add_inset(detail, index, corners.0, corners.1, relative);
 
         let window = gtk::ApplicationWindow::builder()
             .application(app)
@@ -185,17 +1416,44 @@ pub mod demo {
         let da = darea.clone();
         let st = state.clone();
         motion.connect_motion(move |_, x, y| {
-            let cursor = st.borrow().plot.cursor_position(st.borrow().current_rect, x, y);
+            let cursor = st
+                .borrow()
+                .plot
+                .cursor_position(st.borrow().current_rect, x, y);
 
-            if let PlotCursorPosition::Axes(i, axpos) = &cursor {
-                let xy = st.borrow().plot.axes[*i].0.borrow().snap_cursor(*axpos);
-                st.borrow().plot.axes[*i].0.borrow_mut().cursor = xy;
-            }
+            st.borrow_mut().plot.set_cursor(cursor);
             st.borrow_mut().cursor = cursor;
             da.borrow().queue_draw();
         });
         darea.borrow().add_controller(motion);
 
+        // Drag controller for moving annotations
+        let drag = gtk::GestureDrag::new();
+        let st = state.clone();
+        drag.connect_drag_begin(move |_, x, y| {
+            let mut st_borrow = st.borrow_mut();
+            let hit = st_borrow.plot.annotation_at(st_borrow.current_rect, x, y);
+            st_borrow.dragging = hit.map(|a| (a, (0.0, 0.0)));
+        });
+        let da = darea.clone();
+        let st = state.clone();
+        drag.connect_drag_update(move |_, ox, oy| {
+            let mut st_borrow = st.borrow_mut();
+            if let Some((annotation, (last_x, last_y))) = st_borrow.dragging {
+                let rect = st_borrow.current_rect;
+                st_borrow
+                    .plot
+                    .drag_annotation(rect, annotation, ox - last_x, oy - last_y);
+                st_borrow.dragging = Some((annotation, (ox, oy)));
+                da.borrow().queue_draw();
+            }
+        });
+        let st = state.clone();
+        drag.connect_drag_end(move |_, _, _| {
+            st.borrow_mut().dragging = None;
+        });
+        darea.borrow().add_controller(drag);
+
         // Scroll event controller
         let zoom = gtk::EventControllerScroll::new(gtk::EventControllerScrollFlags::VERTICAL);
         let da = darea.clone();
@@ -211,9 +1469,7 @@ pub mod demo {
                 let mut st_borrow = st.borrow_mut();
 
                 if let PlotCursorPosition::Axes(i, axes_pos) = st_borrow.cursor {
-                    if let Some((axes, _)) = st_borrow.plot.axes.get_mut(i) {
-                        axes.borrow_mut().zoom_at(axes_pos, scale);
-                    }
+                    st_borrow.plot.zoom_at(i, axes_pos, scale);
                 }
 
                 da.borrow().queue_draw();
@@ -228,9 +1484,10 @@ pub mod demo {
         let st = state.clone();
         key.connect_key_pressed(move |_, k, _, _| {
             if k == gtk::gdk::Key::from_name("f").unwrap() {
-                for (ax, _) in &st.borrow().plot.axes {
-                    ax.borrow_mut().zoom_fit();
-                }
+                st.borrow_mut().plot.zoom_fit();
+                da.borrow().queue_draw();
+            } else if k == gtk::gdk::Key::from_name("y").unwrap() {
+                st.borrow_mut().plot.zoom_fit_y();
                 da.borrow().queue_draw();
             } else if k == gtk::gdk::Key::from_name("s").unwrap() {
                 st.borrow().plot.export_svg();
@@ -1,14 +1,35 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    path::PathBuf,
+    rc::Rc,
+};
 
 use cairo::Rectangle;
 use gtk::prelude::*;
 use relm4::*;
 
-use crate::{axes::Axes, plot::Plot};
+use crate::{
+    axes::Axes,
+    axis::ScaleMode,
+    eye::{EyeConfig, EyeMeasurements},
+    measure::{MeasureConfig, MeasureJob, Measurements},
+    plot::{Plot, PlotCursorPosition},
+    session::Session,
+    spectrum::{Magnitude, SpectrumMetrics, Window},
+    stream::update_on_frame,
+    theme::{Theme, ThemeError},
+};
 
 pub struct PlotModel {
     hidden: bool,
     plot: Rc<RefCell<Plot>>,
+    da: gtk::DrawingArea,
+    /// whether the frame callback moving streamed data and rendered frames is running
+    ticking: Rc<Cell<bool>>,
+    /// incremented for every measurement run, results of older runs are dropped
+    measure_generation: u64,
+    /// expression for a new derived trace
+    expression: gtk::Entry,
 }
 
 #[derive(Debug)]
@@ -16,16 +37,217 @@ pub enum PlotInput {
     Show,
     Accept,
     Cancel,
+    /// measure all traces in the visible range
+    Measure,
+    /// results of the measurement run with the given generation on a worker thread
+    Measured(u64, Vec<(usize, String, Measurements)>),
+    /// add a trace computed from an expression
+    Derive(String),
+    /// show the spectrum of the visible part of the first trace
+    Spectrum,
+    /// show the eye diagram of the first trace
+    Eye,
+    /// add a live trace fed from a background thread
+    Live,
+    /// show or hide the A and B markers that limit measurements
+    Markers,
+    /// ask for a file name to export the Plot as PNG
+    ExportPng,
+    /// write a PNG of the Plot at print resolution
+    SavePng(PathBuf),
 }
 
 #[derive(Debug)]
 pub enum PlotOutput {
     Close,
+    Measurements(Vec<(String, Measurements)>),
+    Spectrum(SpectrumMetrics),
+    Eye(EyeMeasurements),
+    /// something failed, to be shown to the user
+    Error(String),
+}
+
+/// Pixel rectangle of the whole drawing area
+fn area_rect(da: &gtk::DrawingArea) -> Rectangle {
+    Rectangle::new(0.0, 0.0, da.width() as f64, da.height() as f64)
+}
+
+/// Remember the row heights for the next start
+fn save_session(plot: &Plot) {
+    let session = Session {
+        row_heights: plot.row_heights(),
+    };
+    // losing the layout isn't worth bothering the user about
+    let _ = session.save();
+}
+
+/// Splitters between the Plot rows (drag to resize), double-click to show a row alone,
+/// ctrl-click to collapse a row and click on a collapsed row to expand it
+fn add_row_controllers(plot: &Rc<RefCell<Plot>>, da: &gtk::DrawingArea) {
+    let motion = gtk::EventControllerMotion::new();
+    let (p, area) = (plot.clone(), da.clone());
+    motion.connect_motion(move |_, x, y| {
+        let on_splitter = p.borrow().splitter_at(area_rect(&area), x, y).is_some();
+        area.set_cursor_from_name(on_splitter.then_some("row-resize"));
+    });
+    da.add_controller(motion);
+
+    // row above the splitter being dragged, and the drag offset at the last update
+    let dragging = Rc::new(Cell::new(None::<(usize, f64)>));
+    let drag = gtk::GestureDrag::new();
+    let (p, area, d) = (plot.clone(), da.clone(), dragging.clone());
+    drag.connect_drag_begin(move |_, x, y| {
+        let splitter = p.borrow().splitter_at(area_rect(&area), x, y);
+        d.set(splitter.map(|row| (row, 0.0)));
+    });
+    let (p, area, d) = (plot.clone(), da.clone(), dragging.clone());
+    drag.connect_drag_update(move |_, _, oy| {
+        if let Some((row, last)) = d.get() {
+            p.borrow_mut()
+                .drag_splitter(area_rect(&area), row, oy - last);
+            d.set(Some((row, oy)));
+            area.queue_draw();
+        }
+    });
+    let p = plot.clone();
+    drag.connect_drag_end(move |_, _, _| {
+        if dragging.take().is_some() {
+            save_session(&p.borrow());
+        }
+    });
+    da.add_controller(drag);
+
+    let click = gtk::GestureClick::new();
+    let (p, area) = (plot.clone(), da.clone());
+    click.connect_pressed(move |gesture, n_press, _, y| {
+        let mut plot = p.borrow_mut();
+        let Some(row) = plot.row_at(area_rect(&area), y) else {
+            return;
+        };
+        let ctrl = gesture
+            .current_event_state()
+            .contains(gtk::gdk::ModifierType::CONTROL_MASK);
+        if n_press == 2 {
+            plot.toggle_maximized(row);
+        } else if ctrl || plot.is_row_collapsed(row) {
+            plot.toggle_collapsed(row);
+        } else {
+            return;
+        }
+        area.queue_draw();
+    });
+    da.add_controller(click);
+}
+
+/// Keys "f" (fit all data) and "y" (fit y to the data in the visible x range)
+fn add_key_controller(plot: &Rc<RefCell<Plot>>, da: &gtk::DrawingArea) {
+    da.set_focusable(true);
+    let key = gtk::EventControllerKey::new();
+    let (p, area) = (plot.clone(), da.clone());
+    key.connect_key_pressed(move |_, key, _, _| {
+        if key == gtk::gdk::Key::f {
+            p.borrow_mut().zoom_fit();
+        } else if key == gtk::gdk::Key::y {
+            p.borrow_mut().zoom_fit_y();
+        } else {
+            return gtk::glib::Propagation::Proceed;
+        }
+        area.queue_draw();
+        gtk::glib::Propagation::Stop
+    });
+    da.add_controller(key);
+
+    // take the keyboard focus when clicked
+    let click = gtk::GestureClick::new();
+    let area = da.clone();
+    click.connect_pressed(move |_, _, _, _| {
+        area.grab_focus();
+    });
+    da.add_controller(click);
+}
+
+/// Cursor readouts (snapped trace point with its channels and errors, image cell)
+/// under the pointer, and the time cursor linked from XY Axes
+fn add_cursor_controller(plot: &Rc<RefCell<Plot>>, da: &gtk::DrawingArea) {
+    let motion = gtk::EventControllerMotion::new();
+    let (p, area) = (plot.clone(), da.clone());
+    motion.connect_motion(move |_, x, y| {
+        let mut plot = p.borrow_mut();
+        let position = plot.cursor_position(area_rect(&area), x, y);
+        plot.set_cursor(position);
+        // the cursor is part of the overlay, which isn't cached
+        area.queue_draw();
+    });
+    let (p, area) = (plot.clone(), da.clone());
+    motion.connect_leave(move |_| {
+        p.borrow_mut().set_cursor(PlotCursorPosition::None);
+        area.queue_draw();
+    });
+    da.add_controller(motion);
+}
+
+/// Drag annotations to move them
+fn add_annotation_controller(plot: &Rc<RefCell<Plot>>, da: &gtk::DrawingArea) {
+    // (axes, annotation) being dragged, and the drag offset at the last update
+    let dragging = Rc::new(Cell::new(None::<((usize, usize), (f64, f64))>));
+    let drag = gtk::GestureDrag::new();
+    let (p, area, d) = (plot.clone(), da.clone(), dragging.clone());
+    drag.connect_drag_begin(move |_, x, y| {
+        let hit = p.borrow().annotation_at(area_rect(&area), x, y);
+        d.set(hit.map(|a| (a, (0.0, 0.0))));
+    });
+    let (p, area, d) = (plot.clone(), da.clone(), dragging.clone());
+    drag.connect_drag_update(move |_, ox, oy| {
+        if let Some((annotation, (last_x, last_y))) = d.get() {
+            p.borrow_mut()
+                .drag_annotation(area_rect(&area), annotation, ox - last_x, oy - last_y);
+            d.set(Some((annotation, (ox, oy))));
+            area.queue_draw();
+        }
+    });
+    drag.connect_drag_end(move |_, _, _| {
+        dragging.set(None);
+    });
+    da.add_controller(drag);
+}
+
+/// Use the theme in `theme.toml` in the user config directory if there is one,
+/// otherwise the light or dark theme, following the libadwaita style. Errors in the
+/// theme file are sent as `PlotOutput::Error`.
+fn follow_style(
+    plot: &Rc<RefCell<Plot>>,
+    da: &gtk::DrawingArea,
+    sender: &ComponentSender<PlotModel>,
+) {
+    let path = gtk::glib::user_config_dir()
+        .join("plotter")
+        .join("theme.toml");
+    match Theme::load(&path) {
+        Ok(theme) => {
+            plot.borrow_mut().set_theme(theme);
+            return;
+        }
+        // no custom theme
+        Err(ThemeError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => {
+            let message = format!("{}: {e}", path.display());
+            sender.output(PlotOutput::Error(message)).unwrap();
+        }
+    }
+    let theme_for = |dark: bool| if dark { Theme::dark() } else { Theme::light() };
+    let style = adw::StyleManager::default();
+    plot.borrow_mut().set_theme(theme_for(style.is_dark()));
+    let (p, area) = (plot.clone(), da.clone());
+    style.connect_dark_notify(move |style| {
+        p.borrow_mut().set_theme(theme_for(style.is_dark()));
+        area.queue_draw();
+    });
 }
 
 #[relm4::component(pub)]
 impl SimpleComponent for PlotModel {
-    type Init = ();
+    /// the Axes to show, one row each
+    type Init = Vec<Axes>;
     type Input = PlotInput;
     type Output = PlotOutput;
 
@@ -60,34 +282,210 @@ impl SimpleComponent for PlotModel {
                     set_label: "False",
                     set_group: Some(&truebtn),
                 },
+
+                gtk::Button {
+                    set_label: "A/B",
+                    connect_clicked => PlotInput::Markers,
+                },
+
+                gtk::Button {
+                    set_label: "Measure",
+                    connect_clicked => PlotInput::Measure,
+                },
+
+                gtk::Button {
+                    set_label: "FFT",
+                    connect_clicked => PlotInput::Spectrum,
+                },
+
+                gtk::Button {
+                    set_label: "Eye",
+                    connect_clicked => PlotInput::Eye,
+                },
+
+                gtk::Button {
+                    set_label: "Live",
+                    connect_clicked => PlotInput::Live,
+                },
+
+                gtk::Button {
+                    set_label: "PNG",
+                    connect_clicked => PlotInput::ExportPng,
+                },
+
+                #[name = "expression"]
+                gtk::Entry {
+                    set_placeholder_text: Some("avg(Pulse, 500m)"),
+                    connect_activate[sender] => move |entry| {
+                        sender.input(PlotInput::Derive(entry.text().to_string()));
+                    },
+                },
             }
         }
 
     }
 
     fn init(
-        init: Self::Init,
+        axes: Self::Init,
         root: Self::Root,
         sender: ComponentSender<Self>,
     ) -> ComponentParts<Self> {
-        let model = PlotModel {
+        let mut model = PlotModel {
             hidden: false,
             plot: Rc::new(RefCell::new(Plot::new())),
+            da: gtk::DrawingArea::new(),
+            ticking: Rc::new(Cell::new(false)),
+            measure_generation: 0,
+            expression: gtk::Entry::new(),
         };
 
         let widgets = view_output!();
+        model.da = widgets.da.clone();
+        model.expression = widgets.expression.clone();
 
         let plot = model.plot.clone();
-        let axes1 = Axes::linear(None);
-        plot.borrow_mut().add_axes(axes1.clone());
+        plot.borrow_mut().set_background_rendering(true);
+        for axes in axes {
+            plot.borrow_mut().add_axes(axes);
+        }
+        if let Some(session) = Session::load() {
+            plot.borrow_mut().set_row_heights(&session.row_heights);
+        }
+        add_row_controllers(&plot, &widgets.da);
+        add_key_controller(&plot, &widgets.da);
+        add_cursor_controller(&plot, &widgets.da);
+        add_annotation_controller(&plot, &widgets.da);
+        follow_style(&plot, &widgets.da, &sender);
 
-        widgets.da.set_draw_func(move |_da, cx, width, height| {
-            cx.set_source_rgb(1.0, 0.9, 1.0);
-            cx.paint().unwrap();
+        let ticking = model.ticking.clone();
+        widgets.da.set_draw_func(move |da, cx, width, height| {
             let rect = Rectangle::new(0.0, 0.0, width as f64, height as f64);
+            plot.borrow_mut().set_scale_factor(da.scale_factor() as f64);
             plot.borrow().draw(cx, rect);
+            // drawing may have started background renders
+            update_on_frame(&plot, da, &ticking);
         });
 
         ComponentParts { model, widgets }
     }
+
+    fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>) {
+        match msg {
+            PlotInput::Measure => {
+                // the jobs only hold shared trace data, so the GUI stays responsive
+                let jobs: Vec<MeasureJob> = self.plot.borrow().measure_jobs();
+                self.measure_generation += 1;
+                let generation = self.measure_generation;
+                let sender = sender.clone();
+                std::thread::spawn(move || {
+                    let config = MeasureConfig::default();
+                    let results = jobs
+                        .into_iter()
+                        .filter_map(|job| Some((job.axes, job.name.clone(), job.run(&config)?)))
+                        .collect();
+                    sender.input(PlotInput::Measured(generation, results));
+                });
+            }
+            PlotInput::Markers => {
+                self.plot.borrow_mut().toggle_markers();
+                self.da.queue_draw();
+            }
+            // a newer run was started, its results replace these
+            PlotInput::Measured(generation, _) if generation != self.measure_generation => {}
+            PlotInput::Measured(_, results) => {
+                self.plot.borrow_mut().show_measurements(&results);
+                self.da.queue_draw();
+                let results = results.into_iter().map(|(_, name, m)| (name, m)).collect();
+                sender.output(PlotOutput::Measurements(results)).unwrap();
+            }
+            PlotInput::Derive(text) => {
+                let result = self.plot.borrow_mut().add_derived(0, &text, None);
+                match result {
+                    Ok(()) => {
+                        self.expression.remove_css_class("error");
+                        self.expression.set_tooltip_text(None);
+                        self.expression.set_text("");
+                        self.da.queue_draw();
+                    }
+                    Err(e) => {
+                        self.expression.add_css_class("error");
+                        self.expression.set_tooltip_text(Some(&e.to_string()));
+                    }
+                }
+            }
+            PlotInput::Spectrum => {
+                let mut plot = self.plot.borrow_mut();
+                let metrics = plot.add_spectrum((0, 0), Window::Hann, Magnitude::Db, true);
+                drop(plot);
+                self.da.queue_draw();
+                if let Some(metrics) = metrics {
+                    sender.output(PlotOutput::Spectrum(metrics)).unwrap();
+                }
+            }
+            PlotInput::Eye => {
+                // the example pulses repeat every 2.5 s
+                let mut plot = self.plot.borrow_mut();
+                let measurements = plot.add_eye((0, 0), EyeConfig::new(2.5));
+                drop(plot);
+                self.da.queue_draw();
+                if let Some(measurements) = measurements {
+                    sender.output(PlotOutput::Eye(measurements)).unwrap();
+                }
+            }
+            PlotInput::Live => {
+                let mut axes = Axes::linear();
+                axes.primary_x.scale_mode = ScaleMode::Follow(5.0);
+                axes.primary_y.scale_mode = ScaleMode::AutoVisible;
+                let mut plot = self.plot.borrow_mut();
+                let index = plot.add_axes(axes);
+                let Some(sender) = plot.add_stream(index, "Live", 50_000) else {
+                    return;
+                };
+                drop(plot);
+                update_on_frame(&self.plot, &self.da, &self.ticking);
+                // a noisy 1 Hz sine at 1 kHz sample rate, in batches of 10 ms
+                std::thread::spawn(move || {
+                    let start = std::time::Instant::now();
+                    let mut n = 0u64;
+                    loop {
+                        std::thread::sleep(std::time::Duration::from_millis(10));
+                        let now = start.elapsed().as_secs_f64();
+                        let mut batch = vec![];
+                        while (n as f64) * 1e-3 < now {
+                            let t = n as f64 * 1e-3;
+                            let noise = ((n * 7919) % 101) as f64 / 1000.0;
+                            batch.push((t, (2.0 * std::f64::consts::PI * t).sin() + noise));
+                            n += 1;
+                        }
+                        if sender.send(batch).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            PlotInput::ExportPng => {
+                let dialog = gtk::FileDialog::builder()
+                    .title("Export PNG")
+                    .initial_name("plot.png")
+                    .build();
+                let parent = self.da.root().and_downcast::<gtk::Window>();
+                let sender = sender.clone();
+                dialog.save(parent.as_ref(), gtk::gio::Cancellable::NONE, move |file| {
+                    // an error here means the dialog was cancelled
+                    if let Some(path) = file.ok().and_then(|file| file.path()) {
+                        sender.input(PlotInput::SavePng(path));
+                    }
+                });
+            }
+            PlotInput::SavePng(path) => {
+                // laid out as on screen
+                let size = (self.da.width() as f64, self.da.height() as f64);
+                if let Err(e) = self.plot.borrow().export_png(&path, size, 300.0) {
+                    let message = format!("Can't export {}: {e}", path.display());
+                    sender.output(PlotOutput::Error(message)).unwrap();
+                }
+            }
+            PlotInput::Show | PlotInput::Accept | PlotInput::Cancel => {}
+        }
+    }
 }
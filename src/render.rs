@@ -0,0 +1,461 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+
+use gtk::cairo::{Context, Format, ImageSurface, Rectangle};
+
+use crate::axes::Axes;
+use crate::axis::{Axis, AxisPlacement, AxisType};
+use crate::colormap::{Colorbar, Colormap};
+use crate::theme::Theme;
+use crate::trace::{ErrorStyle, Trace, TraceKind};
+
+/// Everything the trace layer of an Axes depends on. A frame is rendered again
+/// whenever this changes.
+#[derive(Clone, Debug, PartialEq)]
+struct View {
+    x: (AxisType, (f64, f64)),
+    y: (AxisType, (f64, f64)),
+    /// chart size in user space units
+    size: (i32, i32),
+    /// device scale of the target (HiDPI or export resolution)
+    scale: (f64, f64),
+    traces: Vec<TraceView>,
+    /// for the trace colors
+    theme: Theme,
+    /// scatter traces colored by the channel of the colorbar use its scale
+    colorbar: Option<ColorScale>,
+}
+
+/// What the rendering of one trace depends on
+#[derive(Clone, Debug, PartialEq)]
+struct TraceView {
+    /// `Trace::revision` of the points, channels and errors
+    revision: u64,
+    kind: TraceKind,
+    errors: Option<ErrorStyle>,
+}
+
+/// The parts of a colorbar that scatter colors depend on
+#[derive(Clone, Debug, PartialEq)]
+struct ColorScale {
+    name: String,
+    colormap: Colormap,
+    axis: (AxisType, (f64, f64)),
+}
+
+impl View {
+    fn new(axes: &Axes, size: (i32, i32), scale: (f64, f64)) -> Self {
+        Self {
+            x: (axes.primary_x.axis_type(), axes.primary_x.range()),
+            y: (axes.primary_y.axis_type(), axes.primary_y.range()),
+            size,
+            scale,
+            traces: axes
+                .traces
+                .iter()
+                .map(|t| TraceView {
+                    revision: t.revision(),
+                    kind: t.kind.clone(),
+                    errors: t.errors.as_ref().map(|e| e.style),
+                })
+                .collect(),
+            theme: axes.theme.clone(),
+            colorbar: axes.colorbar.as_ref().map(|cb| ColorScale {
+                name: cb.name.clone(),
+                colormap: cb.colormap,
+                axis: (cb.axis.axis_type(), cb.axis.range()),
+            }),
+        }
+    }
+
+    /// Size of the surface in device pixels
+    fn pixels(&self) -> (i32, i32) {
+        let (width, height) = self.size;
+        (
+            (width as f64 * self.scale.0).ceil() as i32,
+            (height as f64 * self.scale.1).ceil() as i32,
+        )
+    }
+}
+
+/// A completed rendering of the traces of one Axes
+struct Frame {
+    view: View,
+    surface: ImageSurface,
+}
+
+/// Pixels of a frame, sent back from a worker thread (cairo surfaces can't be sent)
+struct Rendered {
+    axes: usize,
+    generation: u64,
+    view: View,
+    data: Vec<u8>,
+    stride: i32,
+}
+
+/// Render state of one Axes
+#[derive(Default)]
+struct AxesLayer {
+    /// incremented for every new request, workers of older requests give up
+    generation: Arc<AtomicU64>,
+    requested: Option<View>,
+    frame: Option<Frame>,
+}
+
+/// Jobs waiting for the worker thread, at most one per Axes
+#[derive(Default)]
+struct Queue {
+    jobs: Vec<Job>,
+    /// the Renderer is gone, the worker exits
+    stopped: bool,
+}
+
+/// Queue shared with the worker thread, which waits on `ready` while it is empty
+#[derive(Default)]
+struct Slot {
+    queue: Mutex<Queue>,
+    ready: Condvar,
+}
+
+impl Slot {
+    /// Queue a job, replacing the one waiting for the same Axes
+    fn put(&self, job: Job) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.jobs.retain(|waiting| waiting.axes != job.axes);
+        queue.jobs.push(job);
+        self.ready.notify_one();
+    }
+
+    /// Wait for the next job, None once stopped
+    fn take(&self) -> Option<Job> {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            if queue.stopped {
+                return None;
+            }
+            if !queue.jobs.is_empty() {
+                return Some(queue.jobs.remove(0));
+            }
+            queue = self.ready.wait(queue).unwrap();
+        }
+    }
+
+    fn stop(&self) {
+        self.queue.lock().unwrap().stopped = true;
+        self.ready.notify_one();
+    }
+}
+
+/// Renders the traces of each Axes into an offscreen surface on a worker thread.
+/// Until a frame for the current view is done, the last completed one is shown,
+/// scaled to the current axis ranges.
+pub struct Renderer {
+    layers: RefCell<Vec<AxesLayer>>,
+    /// latest job of each Axes, for the worker
+    slot: Arc<Slot>,
+    receiver: Receiver<Rendered>,
+}
+
+impl Renderer {
+    pub fn new() -> Self {
+        let (sender, receiver) = channel();
+        let slot = Arc::new(Slot::default());
+        let worker_slot = slot.clone();
+        std::thread::spawn(move || {
+            while let Some(job) = worker_slot.take() {
+                job.run(&sender);
+            }
+        });
+        Self {
+            layers: RefCell::new(vec![]),
+            slot,
+            receiver,
+        }
+    }
+
+    /// Paint the latest frame of the Axes at `index` into `chart`, and start rendering
+    /// a new one if the view changed since the last request
+    pub fn draw_layer(&self, cx: &Context, chart: Rectangle, index: usize, axes: &Axes) {
+        let size = (chart.width().round() as i32, chart.height().round() as i32);
+        if size.0 <= 0 || size.1 <= 0 {
+            return;
+        }
+        let view = View::new(axes, size, cx.target().device_scale());
+        let mut layers = self.layers.borrow_mut();
+        if layers.len() <= index {
+            layers.resize_with(index + 1, AxesLayer::default);
+        }
+        let layer = &mut layers[index];
+
+        if layer.requested.as_ref() != Some(&view) {
+            let generation = layer.generation.fetch_add(1, Ordering::Relaxed) + 1;
+            layer.requested = Some(view.clone());
+            let job = Job {
+                axes: index,
+                generation,
+                current: layer.generation.clone(),
+                view,
+                traces: axes.traces.clone(),
+            };
+            self.slot.put(job);
+        }
+
+        if let Some(frame) = &layer.frame {
+            frame.paint(cx, chart, &axes.primary_x, &axes.primary_y);
+        }
+    }
+
+    /// Take over the frames finished since the last call. Returns whether a frame for
+    /// the current view arrived, so the area has to be redrawn.
+    pub fn poll(&self) -> bool {
+        let mut layers = self.layers.borrow_mut();
+        let mut changed = false;
+        for rendered in self.receiver.try_iter() {
+            let Some(layer) = layers.get_mut(rendered.axes) else {
+                continue;
+            };
+            // stale frames from renders that finished before noticing they were replaced
+            if rendered.generation != layer.generation.load(Ordering::Relaxed) {
+                continue;
+            }
+            let (width, height) = rendered.view.pixels();
+            let surface = ImageSurface::create_for_data(
+                rendered.data,
+                Format::ARgb32,
+                width,
+                height,
+                rendered.stride,
+            );
+            if let Ok(surface) = surface {
+                surface.set_device_scale(rendered.view.scale.0, rendered.view.scale.1);
+                layer.frame = Some(Frame {
+                    view: rendered.view,
+                    surface,
+                });
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Whether a requested frame hasn't arrived yet
+    pub fn pending(&self) -> bool {
+        self.layers.borrow().iter().any(|layer| {
+            let shown = layer.frame.as_ref().map(|frame| &frame.view);
+            layer.requested.is_some() && layer.requested.as_ref() != shown
+        })
+    }
+}
+
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        self.slot.stop();
+    }
+}
+
+impl Frame {
+    /// Paint the frame with its data ranges mapped onto the current axes
+    fn paint(&self, cx: &Context, chart: Rectangle, x: &Axis, y: &Axis) {
+        let (x0, x1) = self.view.x.1;
+        let (y0, y1) = self.view.y.1;
+        let left = chart.x() + chart.width() * x.data_to_axis(x0);
+        let right = chart.x() + chart.width() * x.data_to_axis(x1);
+        let top = chart.y() + chart.height() * (1.0 - y.data_to_axis(y1));
+        let bottom = chart.y() + chart.height() * (1.0 - y.data_to_axis(y0));
+        let (width, height) = self.view.size;
+        let scale = (
+            (right - left) / width as f64,
+            (bottom - top) / height as f64,
+        );
+        if !(scale.0.is_finite() && scale.1.is_finite()) || scale.0 <= 0.0 || scale.1 <= 0.0 {
+            return;
+        }
+
+        cx.save().unwrap();
+        cx.translate(left, top);
+        cx.scale(scale.0, scale.1);
+        cx.set_source_surface(&self.surface, 0.0, 0.0).unwrap();
+        cx.paint().unwrap();
+        cx.restore().unwrap();
+    }
+}
+
+/// Snapshot of the traces of an Axes to render on a worker thread
+struct Job {
+    axes: usize,
+    generation: u64,
+    /// generation of the latest request for the same Axes
+    current: Arc<AtomicU64>,
+    view: View,
+    traces: Vec<Trace>,
+}
+
+impl Job {
+    fn cancelled(&self) -> bool {
+        self.current.load(Ordering::Relaxed) != self.generation
+    }
+
+    /// Draw the traces one by one, stopping once a newer request came in
+    fn run(mut self, sender: &Sender<Rendered>) {
+        let (pixel_width, pixel_height) = self.view.pixels();
+        let Ok(mut surface) = ImageSurface::create(Format::ARgb32, pixel_width, pixel_height)
+        else {
+            return;
+        };
+        surface.set_device_scale(self.view.scale.0, self.view.scale.1);
+        let (width, height) = self.view.size;
+        {
+            let Ok(cx) = Context::new(&surface) else {
+                return;
+            };
+            let (x_type, x_range) = self.view.x;
+            let (y_type, y_range) = self.view.y;
+            let mut axes = Axes::new(
+                Axis::new(AxisPlacement::Bottom, x_type, x_range),
+                Axis::new(AxisPlacement::Left, y_type, y_range),
+            );
+            axes.traces = std::mem::take(&mut self.traces);
+            axes.theme = self.view.theme.clone();
+            axes.colorbar = self.view.colorbar.as_ref().map(|scale| {
+                let mut colorbar = Colorbar::new(scale.colormap, scale.axis.1, &scale.name);
+                colorbar.axis = Axis::new(AxisPlacement::Right, scale.axis.0, scale.axis.1);
+                colorbar
+            });
+            let chart = Rectangle::new(0.0, 0.0, width as f64, height as f64);
+            cx.rectangle(0.0, 0.0, width as f64, height as f64);
+            cx.clip();
+            for i in 0..axes.traces.len() {
+                if self.cancelled() {
+                    return;
+                }
+                axes.draw_trace(&cx, chart, i, &|| self.cancelled());
+            }
+        }
+        surface.flush();
+        if self.cancelled() {
+            return;
+        }
+        let stride = surface.stride();
+        let Ok(data) = surface.data() else {
+            return;
+        };
+        let _ = sender.send(Rendered {
+            axes: self.axes,
+            generation: self.generation,
+            view: self.view,
+            data: data.to_vec(),
+            stride,
+        });
+    }
+}
+
+/// Layers of a Plot that are kept between draws. The overlay (cursors, annotations) on
+/// top of them is cheap and drawn every time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CachedLayer {
+    /// background, axes, grid and colorbars
+    Background,
+    /// images and traces
+    Traces,
+    /// everything but the overlay of the inset Axes with this index
+    Inset(usize),
+}
+
+/// Offscreen surface of a cached layer, the area (x, y, width, height) it covers and
+/// its device scale
+struct CachedSurface {
+    area: (f64, f64, f64, f64),
+    scale: f64,
+    surface: ImageSurface,
+}
+
+/// Offscreen surfaces of the cached layers, each valid until invalidated or until the
+/// area or the axis ranges change
+pub struct LayerCache {
+    surfaces: RefCell<HashMap<CachedLayer, CachedSurface>>,
+    /// axis ranges the layers were drawn with, see `set_ranges`
+    ranges: RefCell<Vec<Option<(f64, f64)>>>,
+}
+
+impl LayerCache {
+    pub fn new() -> Self {
+        Self {
+            surfaces: RefCell::new(HashMap::new()),
+            ranges: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn invalidate(&self, layer: CachedLayer) {
+        self.surfaces.borrow_mut().remove(&layer);
+    }
+
+    pub fn invalidate_insets(&self) {
+        let mut surfaces = self.surfaces.borrow_mut();
+        surfaces.retain(|layer, _| !matches!(layer, CachedLayer::Inset(_)));
+    }
+
+    pub fn invalidate_all(&self) {
+        self.surfaces.borrow_mut().clear();
+    }
+
+    /// Axis ranges for the next draw. All layers depend on them, so they are invalidated
+    /// if the ranges differ from the last ones.
+    pub fn set_ranges(&self, ranges: Vec<Option<(f64, f64)>>) {
+        let mut drawn = self.ranges.borrow_mut();
+        if *drawn != ranges {
+            self.invalidate_all();
+            *drawn = ranges;
+        }
+    }
+
+    /// Paint a layer covering `rect`, calling `draw` first to fill it if there is no
+    /// valid surface for it. The surface has `scale` device pixels per unit, the scale
+    /// factor of the widget on HiDPI displays: the target of a GTK draw function is a
+    /// recording surface that doesn't know it.
+    pub fn paint(
+        &self,
+        cx: &Context,
+        rect: Rectangle,
+        scale: f64,
+        layer: CachedLayer,
+        draw: impl FnOnce(&Context),
+    ) {
+        let area = (rect.x(), rect.y(), rect.width(), rect.height());
+        let mut surfaces = self.surfaces.borrow_mut();
+        let stale = match surfaces.get(&layer) {
+            Some(cached) => cached.area != area || cached.scale != scale,
+            None => true,
+        };
+        if stale {
+            let size = (
+                (rect.width() * scale).ceil() as i32,
+                (rect.height() * scale).ceil() as i32,
+            );
+            let Ok(surface) = ImageSurface::create(Format::ARgb32, size.0, size.1) else {
+                return;
+            };
+            surface.set_device_scale(scale, scale);
+            {
+                let Ok(layer_cx) = Context::new(&surface) else {
+                    return;
+                };
+                layer_cx.translate(-rect.x(), -rect.y());
+                draw(&layer_cx);
+            }
+            let cached = CachedSurface {
+                area,
+                scale,
+                surface,
+            };
+            surfaces.insert(layer, cached);
+        }
+        if let Some(cached) = surfaces.get(&layer) {
+            cx.set_source_surface(&cached.surface, rect.x(), rect.y())
+                .unwrap();
+            cx.paint().unwrap();
+        }
+    }
+}
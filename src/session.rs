@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// View state kept between runs, stored as a TOML file in the user config directory
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// relative heights of the Plot rows
+    #[serde(deserialize_with = "row_heights")]
+    pub row_heights: Vec<f64>,
+}
+
+/// Row heights that keep their position: entries that aren't positive numbers become 1
+fn row_heights<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<f64>, D::Error> {
+    let values = Vec::<toml::Value>::deserialize(deserializer)?;
+    let height = |value: &toml::Value| match value {
+        toml::Value::Float(h) => Some(*h),
+        toml::Value::Integer(h) => Some(*h as f64),
+        _ => None,
+    };
+    Ok(values
+        .iter()
+        .map(|value| match height(value) {
+            Some(h) if h.is_finite() && h > 0.0 => h,
+            _ => 1.0,
+        })
+        .collect())
+}
+
+impl Session {
+    fn path() -> PathBuf {
+        gtk::glib::user_config_dir()
+            .join("plotter")
+            .join("session.toml")
+    }
+
+    /// The saved session, None if there is none or it can't be read. Unknown keys are
+    /// skipped.
+    pub fn load() -> Option<Self> {
+        let text = std::fs::read_to_string(Self::path()).ok()?;
+        Self::from_toml(&text)
+    }
+
+    fn from_toml(text: &str) -> Option<Self> {
+        toml::from_str(text).ok()
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let text = toml::to_string(self).map_err(std::io::Error::other)?;
+        std::fs::write(path, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_heights_keep_their_row() {
+        let session = Session::from_toml("row_heights = [2.0, -1.0, \"x\", 3, nan]").unwrap();
+        assert_eq!(session.row_heights, vec![2.0, 1.0, 1.0, 3.0, 1.0]);
+    }
+
+    #[test]
+    fn saved_heights_load_again() {
+        let session = Session {
+            row_heights: vec![0.5, 1.5],
+        };
+        let loaded = Session::from_toml(&toml::to_string(&session).unwrap()).unwrap();
+        assert_eq!(loaded.row_heights, session.row_heights);
+        assert!(Session::from_toml("other = 1")
+            .unwrap()
+            .row_heights
+            .is_empty());
+    }
+}
@@ -0,0 +1,367 @@
+use std::f64::consts::PI;
+
+use crate::cairo_utils::format_number;
+use crate::trace::{Trace, XOrder};
+
+/// Window applied to the samples before the FFT
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Hamming,
+    BlackmanHarris,
+    /// accurate amplitudes, wide main lobe
+    FlatTop,
+}
+
+impl Window {
+    fn cosine_terms(&self) -> &'static [f64] {
+        match self {
+            Window::Rectangular => &[1.0],
+            Window::Hann => &[0.5, 0.5],
+            Window::Hamming => &[0.54, 0.46],
+            Window::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+            Window::FlatTop => &[
+                0.21557895,
+                0.41663158,
+                0.277263158,
+                0.083578947,
+                0.006947368,
+            ],
+        }
+    }
+
+    /// Window value for sample `i` of `n` (periodic form, as used for spectral analysis)
+    pub fn coefficient(&self, i: usize, n: usize) -> f64 {
+        let phase = 2.0 * PI * i as f64 / n as f64;
+        self.cosine_terms()
+            .iter()
+            .enumerate()
+            .map(|(k, a)| {
+                let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                sign * a * (k as f64 * phase).cos()
+            })
+            .sum()
+    }
+
+    /// Half width of the main lobe in bins, used to collect the power of a tone
+    fn lobe_bins(&self) -> usize {
+        match self {
+            Window::Rectangular => 1,
+            Window::Hann | Window::Hamming => 2,
+            Window::BlackmanHarris => 4,
+            Window::FlatTop => 5,
+        }
+    }
+}
+
+/// Scale of the magnitude trace
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Magnitude {
+    Linear,
+    /// dB relative to 1 (dBV for a voltage)
+    Db,
+}
+
+/// In-place iterative radix-2 FFT, `re.len()` must be a power of two
+fn fft(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+    debug_assert!(n.is_power_of_two());
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (s, c) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * c - im[b] * s;
+                let t_im = re[b] * s + im[b] * c;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Single-sided spectrum of a uniformly resampled trace
+#[derive(Clone, Debug)]
+pub struct Spectrum {
+    pub window: Window,
+    /// bin spacing in Hz (1 / duration)
+    pub resolution: f64,
+    /// peak amplitude of a sine at each bin, corrected for the window gain
+    pub magnitude: Vec<f64>,
+    /// phase in degrees
+    pub phase: Vec<f64>,
+}
+
+/// Figures of merit of a single-tone spectrum, in dB (ENOB in bits)
+#[derive(Clone, Debug)]
+pub struct SpectrumMetrics {
+    /// frequency and amplitude of the strongest tone (use a flat-top window for an
+    /// accurate amplitude)
+    pub fundamental: (f64, f64),
+    pub thd: f64,
+    pub snr: f64,
+    pub sinad: f64,
+    pub sfdr: f64,
+    pub enob: f64,
+}
+
+impl Spectrum {
+    /// Spectrum of a trace sorted by x (time), optionally only inside an x range. The
+    /// samples are linearly resampled to the next power of two at least as long as the
+    /// input. Returns None if the trace isn't sorted by x or fewer than two finite
+    /// samples are in the range.
+    pub fn new(trace: &Trace, range: Option<(f64, f64)>, window: Window) -> Option<Self> {
+        // the resampling walks the samples in order
+        if trace.order != XOrder::Sorted {
+            return None;
+        }
+        let values: Vec<_> = trace
+            .values
+            .iter()
+            .copied()
+            .filter(|(x, y)| x.is_finite() && y.is_finite())
+            .filter(|(x, _)| match range {
+                Some((x0, x1)) => (x0..=x1).contains(x),
+                None => true,
+            })
+            .collect();
+        if values.len() < 2 {
+            return None;
+        }
+        let (t0, t1) = (values[0].0, values[values.len() - 1].0);
+        if t1 <= t0 {
+            return None;
+        }
+
+        let n = values.len().next_power_of_two();
+        let dt = (t1 - t0) / n as f64;
+        let mut j = 0;
+        let mut re: Vec<f64> = (0..n)
+            .map(|i| {
+                let t = t0 + i as f64 * dt;
+                while j + 2 < values.len() && values[j + 1].0 < t {
+                    j += 1;
+                }
+                let ((x0, y0), (x1, y1)) = (values[j], values[j + 1]);
+                let y = if x1 > x0 {
+                    y0 + (t - x0) / (x1 - x0) * (y1 - y0)
+                } else {
+                    y0
+                };
+                y * window.coefficient(i, n)
+            })
+            .collect();
+        let mut im = vec![0.0; n];
+        fft(&mut re, &mut im);
+
+        let gain: f64 = (0..n).map(|i| window.coefficient(i, n)).sum();
+        let (magnitude, phase) = (0..=n / 2)
+            .map(|k| {
+                // the energy of bins other than DC and Nyquist is split over ±f
+                let scale = if k == 0 || k == n / 2 { 1.0 } else { 2.0 } / gain;
+                (scale * re[k].hypot(im[k]), im[k].atan2(re[k]).to_degrees())
+            })
+            .unzip();
+        Some(Self {
+            window,
+            resolution: 1.0 / (t1 - t0),
+            magnitude,
+            phase,
+        })
+    }
+
+    pub fn frequency(&self, bin: usize) -> f64 {
+        bin as f64 * self.resolution
+    }
+
+    /// Magnitude over frequency. The DC bin is left out so the trace can be shown on a
+    /// logarithmic frequency axis.
+    pub fn magnitude_trace(&self, scale: Magnitude, name: &str) -> Trace {
+        let values = self
+            .magnitude
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(k, m)| {
+                let m = match scale {
+                    Magnitude::Linear => *m,
+                    Magnitude::Db => 20.0 * m.max(1e-300).log10(),
+                };
+                (self.frequency(k), m)
+            })
+            .collect();
+        Trace::new(values, name)
+    }
+
+    /// Phase in degrees over frequency, without the DC bin
+    pub fn phase_trace(&self, name: &str) -> Trace {
+        let values = self
+            .phase
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(k, p)| (self.frequency(k), *p))
+            .collect();
+        Trace::new(values, name)
+    }
+
+    /// Bins in the main lobe of a tone at `bin`
+    fn lobe(&self, bin: usize) -> std::ops::Range<usize> {
+        let w = self.window.lobe_bins();
+        bin.saturating_sub(w)..(bin + w + 1).min(self.magnitude.len())
+    }
+
+    /// THD (from the first `harmonics` harmonics), SNR, SINAD, SFDR and ENOB, taking the
+    /// strongest non-DC bin as the fundamental. Harmonics above Nyquist are folded back.
+    /// Returns None if the spectrum is too short.
+    pub fn metrics(&self, harmonics: usize) -> Option<SpectrumMetrics> {
+        let dc = self.lobe(0);
+        let (fundamental, _) = self
+            .magnitude
+            .iter()
+            .enumerate()
+            .skip(dc.end)
+            .max_by(|a, b| a.1.total_cmp(b.1))?;
+        let signal = self.lobe(fundamental);
+
+        // mark the bins that belong to DC, the fundamental and each harmonic
+        let n = 2 * (self.magnitude.len() - 1);
+        let mut used = vec![false; self.magnitude.len()];
+        let mark = |bins: std::ops::Range<usize>, used: &mut Vec<bool>| {
+            let fresh: Vec<_> = bins.filter(|b| !used[*b]).collect();
+            fresh.iter().for_each(|b| used[*b] = true);
+            fresh
+                .iter()
+                .map(|b| self.magnitude[*b].powi(2))
+                .sum::<f64>()
+        };
+        mark(dc, &mut used);
+        let signal_power = mark(signal.clone(), &mut used);
+        let mut harmonic_power = 0.0;
+        for h in 2..=harmonics + 1 {
+            let bin = (h * fundamental) % n;
+            let bin = if bin > n / 2 { n - bin } else { bin };
+            // the harmonic may be off by a bin or two due to leakage
+            let peak = self
+                .lobe(bin)
+                .max_by(|a, b| self.magnitude[*a].total_cmp(&self.magnitude[*b]))?;
+            harmonic_power += mark(self.lobe(peak), &mut used);
+        }
+        let noise_power: f64 = (0..self.magnitude.len())
+            .filter(|b| !used[*b])
+            .map(|b| self.magnitude[b].powi(2))
+            .sum();
+
+        // largest spur outside of DC and the fundamental
+        let spur = (self.lobe(0).end..self.magnitude.len())
+            .filter(|b| !signal.contains(b))
+            .map(|b| self.magnitude[b])
+            .fold(0.0, f64::max);
+
+        let db = |ratio: f64| 10.0 * ratio.log10();
+        let sinad = db(signal_power / (noise_power + harmonic_power));
+        Some(SpectrumMetrics {
+            fundamental: (self.frequency(fundamental), self.magnitude[fundamental]),
+            thd: db(harmonic_power / signal_power),
+            snr: db(signal_power / noise_power),
+            sinad,
+            sfdr: db(self.magnitude[fundamental].powi(2) / (spur * spur)),
+            enob: (sinad - 1.76) / 6.02,
+        })
+    }
+}
+
+impl SpectrumMetrics {
+    /// (name, formatted value) rows for a measurement table
+    pub fn table(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("Fund.", format_number(self.fundamental.0)),
+            ("Amplitude", format_number(self.fundamental.1)),
+            ("THD", format!("{:.1} dB", self.thd)),
+            ("SNR", format!("{:.1} dB", self.snr)),
+            ("SINAD", format!("{:.1} dB", self.sinad)),
+            ("SFDR", format!("{:.1} dB", self.sfdr)),
+            ("ENOB", format!("{:.2} bit", self.enob)),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOWS: [Window; 5] = [
+        Window::Rectangular,
+        Window::Hann,
+        Window::Hamming,
+        Window::BlackmanHarris,
+        Window::FlatTop,
+    ];
+
+    /// One second sampled at 1024 Hz, a sum of sines given as (frequency, amplitude)
+    fn tones(tones: &[(f64, f64)]) -> Trace {
+        let values = (0..=1024)
+            .map(|i| {
+                let t = i as f64 / 1024.0;
+                let y = tones
+                    .iter()
+                    .map(|(f, a)| a * (2.0 * PI * f * t).sin())
+                    .sum();
+                (t, y)
+            })
+            .collect();
+        Trace::new(values, "tones")
+    }
+
+    #[test]
+    fn pure_tone_bin_and_amplitude() {
+        let trace = tones(&[(8.0, 2.0)]);
+        for window in WINDOWS {
+            let spectrum = Spectrum::new(&trace, None, window).unwrap();
+            assert_eq!(spectrum.resolution, 1.0);
+            let metrics = spectrum.metrics(5).unwrap();
+            let (frequency, amplitude) = metrics.fundamental;
+            assert_eq!(frequency, 8.0, "{window:?}");
+            assert!((amplitude - 2.0).abs() < 1e-2, "{window:?}: {amplitude}");
+        }
+    }
+
+    #[test]
+    fn known_thd() {
+        // harmonics of -20 dB and -40 dB: THD = 10 log10(0.1² + 0.01²)
+        let trace = tones(&[(8.0, 1.0), (16.0, 0.1), (24.0, 0.01)]);
+        let spectrum = Spectrum::new(&trace, None, Window::Hann).unwrap();
+        let metrics = spectrum.metrics(5).unwrap();
+        let expected = 10.0 * (0.1f64.powi(2) + 0.01f64.powi(2)).log10();
+        assert!((metrics.thd - expected).abs() < 0.1, "{}", metrics.thd);
+        assert!((metrics.sfdr - 20.0).abs() < 0.1, "{}", metrics.sfdr);
+        assert!(metrics.snr > 60.0, "{}", metrics.snr);
+    }
+
+    #[test]
+    fn unsorted_trace_has_no_spectrum() {
+        let values = vec![(0.0, 0.0), (2.0, 1.0), (1.0, 0.0), (3.0, 1.0)];
+        let trace = Trace::new(values, "loop");
+        assert!(Spectrum::new(&trace, None, Window::Hann).is_none());
+    }
+}
@@ -0,0 +1,90 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+
+use gtk::prelude::*;
+
+use crate::plot::Plot;
+
+/// Sending half of a stream, can be cloned and moved to other threads. Sending fails
+/// once the Plot is gone, which producers can use to stop.
+pub type StreamSender = Sender<Vec<(f64, f64)>>;
+
+/// Receiving end of a stream, feeding a streaming trace of a Plot
+pub struct Stream {
+    receiver: Receiver<Vec<(f64, f64)>>,
+    /// (Axes index, trace index) of the trace
+    pub target: (usize, usize),
+    /// all senders are gone, no more samples will arrive
+    closed: bool,
+}
+
+impl Stream {
+    pub fn new(target: (usize, usize)) -> (Self, StreamSender) {
+        let (sender, receiver) = channel();
+        let stream = Self {
+            receiver,
+            target,
+            closed: false,
+        };
+        (stream, sender)
+    }
+
+    /// All samples received since the last call, without blocking
+    pub fn poll(&mut self) -> Vec<(f64, f64)> {
+        let mut samples = vec![];
+        loop {
+            match self.receiver.try_recv() {
+                Ok(batch) => samples.extend(batch),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.closed = true;
+                    break;
+                }
+            }
+        }
+        samples
+    }
+
+    /// Stop taking samples, the stream is dropped and its senders fail from then on
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+/// Move streamed samples (and frames rendered in the background) into the Plot once per
+/// frame and redraw only if something new arrived, so redraws are throttled to the
+/// frame clock no matter how often the producers send.
+///
+/// The frame callback only runs while the Plot has streams or background renders
+/// pending, call this again after adding a stream or drawing. `ticking` tells whether
+/// the callback is running, so it is added only once.
+pub fn update_on_frame(
+    plot: &Rc<RefCell<Plot>>,
+    area: &gtk::DrawingArea,
+    ticking: &Rc<Cell<bool>>,
+) {
+    if ticking.get() || !plot.borrow().is_busy() {
+        return;
+    }
+    ticking.set(true);
+    let (plot, ticking) = (plot.clone(), ticking.clone());
+    area.add_tick_callback(move |area, _clock| {
+        let mut plot = plot.borrow_mut();
+        let streamed = plot.poll_streams();
+        let rendered = plot.poll_renders();
+        if streamed || rendered {
+            area.queue_draw();
+        }
+        if plot.is_busy() {
+            gtk::glib::ControlFlow::Continue
+        } else {
+            ticking.set(false);
+            gtk::glib::ControlFlow::Break
+        }
+    });
+}
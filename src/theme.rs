@@ -0,0 +1,213 @@
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// RGB color, components from 0 to 1
+pub type Color = (f64, f64, f64);
+
+/// Colors of a Plot. In a TOML file colors are `[r, g, b]` arrays; `base = "dark"`
+/// starts from a built-in theme, and keys that are left out keep its colors.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    /// around the charts
+    pub background: Color,
+    /// inside the charts
+    pub chart: Color,
+    /// axis lines, ticks and chart outlines
+    pub axis: Color,
+    pub grid_major: Color,
+    pub grid_minor: Color,
+    pub text: Color,
+    pub cursor: Color,
+    /// line at the time linked from the cursor of an XY Axes
+    pub time_cursor: Color,
+    /// mask limits and regions
+    pub mask: Color,
+    /// trace segments violating the mask
+    pub violation: Color,
+    /// trace colors, used in turn
+    pub palette: Vec<Color>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::light()
+    }
+}
+
+impl Theme {
+    pub fn light() -> Self {
+        Self {
+            background: (1.0, 1.0, 1.0),
+            chart: (1.0, 1.0, 1.0),
+            axis: (0.0, 0.0, 0.0),
+            grid_major: (0.8, 0.8, 0.8),
+            grid_minor: (0.925, 0.925, 0.925),
+            text: (0.0, 0.0, 0.0),
+            cursor: (0.0, 0.0, 0.0),
+            time_cursor: (0.8, 0.1, 0.1),
+            mask: (0.8, 0.0, 0.0),
+            violation: (1.0, 0.0, 0.0),
+            palette: vec![
+                (1.0, 0.6, 0.0),
+                (0.12, 0.47, 0.71),
+                (0.17, 0.63, 0.17),
+                (0.84, 0.15, 0.16),
+                (0.58, 0.4, 0.74),
+                (0.55, 0.34, 0.29),
+            ],
+        }
+    }
+
+    /// Matches the dark libadwaita window colors
+    pub fn dark() -> Self {
+        Self {
+            background: (0.14, 0.14, 0.14),
+            chart: (0.11, 0.11, 0.11),
+            axis: (0.8, 0.8, 0.8),
+            grid_major: (0.3, 0.3, 0.3),
+            grid_minor: (0.19, 0.19, 0.19),
+            text: (0.92, 0.92, 0.92),
+            cursor: (0.92, 0.92, 0.92),
+            time_cursor: (1.0, 0.4, 0.4),
+            mask: (0.95, 0.3, 0.3),
+            violation: (1.0, 0.2, 0.2),
+            palette: vec![
+                (1.0, 0.65, 0.2),
+                (0.4, 0.7, 1.0),
+                (0.5, 0.85, 0.4),
+                (1.0, 0.45, 0.45),
+                (0.75, 0.6, 1.0),
+                (0.9, 0.75, 0.5),
+            ],
+        }
+    }
+
+    /// Black on white with a darker grid and saturated trace colors
+    pub fn high_contrast() -> Self {
+        Self {
+            grid_major: (0.5, 0.5, 0.5),
+            grid_minor: (0.75, 0.75, 0.75),
+            palette: vec![
+                (0.0, 0.0, 0.8),
+                (0.8, 0.0, 0.0),
+                (0.0, 0.5, 0.0),
+                (0.6, 0.0, 0.6),
+                (0.0, 0.0, 0.0),
+            ],
+            ..Self::light()
+        }
+    }
+
+    /// For paper: a light grid and dark trace colors that stay apart in grayscale
+    pub fn print() -> Self {
+        Self {
+            grid_major: (0.85, 0.85, 0.85),
+            grid_minor: (0.95, 0.95, 0.95),
+            palette: vec![
+                (0.0, 0.0, 0.0),
+                (0.0, 0.27, 0.6),
+                (0.7, 0.1, 0.1),
+                (0.5, 0.5, 0.5),
+                (0.0, 0.45, 0.2),
+            ],
+            ..Self::light()
+        }
+    }
+
+    /// Built-in theme by name: "light", "dark", "high-contrast" or "print"
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "light" => Some(Self::light()),
+            "dark" => Some(Self::dark()),
+            "high-contrast" => Some(Self::high_contrast()),
+            "print" => Some(Self::print()),
+            _ => None,
+        }
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, ThemeError> {
+        let mut table: toml::Table = text.parse().map_err(ThemeError::Parse)?;
+        let base = match table.remove("base") {
+            None => Self::light(),
+            Some(toml::Value::String(name)) => {
+                Self::builtin(&name).ok_or(ThemeError::UnknownBase(name))?
+            }
+            Some(other) => return Err(ThemeError::UnknownBase(other.to_string())),
+        };
+        let Ok(toml::Value::Table(mut theme)) = toml::Value::try_from(base) else {
+            unreachable!("a theme is a TOML table");
+        };
+        theme.extend(table);
+        toml::Value::Table(theme)
+            .try_into()
+            .map_err(ThemeError::Parse)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ThemeError> {
+        let text = std::fs::read_to_string(path).map_err(ThemeError::Io)?;
+        Self::from_toml(&text)
+    }
+
+    /// Color of the i-th trace of an Axes
+    pub fn trace_color(&self, i: usize) -> Color {
+        match self.palette.len() {
+            0 => self.text,
+            n => self.palette[i % n],
+        }
+    }
+}
+
+/// Error from loading a theme file
+#[derive(Debug)]
+pub enum ThemeError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    /// `base` is not the name of a built-in theme
+    UnknownBase(String),
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ThemeError::Io(e) => write!(f, "can't read theme: {e}"),
+            ThemeError::Parse(e) => write!(f, "invalid theme: {e}"),
+            ThemeError::UnknownBase(name) => write!(f, "unknown base theme {name}"),
+        }
+    }
+}
+
+impl std::error::Error for ThemeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn override_keeps_base_colors() {
+        let theme = Theme::from_toml("base = \"dark\"\ncursor = [1.0, 0.0, 0.5]").unwrap();
+        assert_eq!(theme.cursor, (1.0, 0.0, 0.5));
+        assert_eq!(theme.chart, Theme::dark().chart);
+        assert_eq!(theme.palette, Theme::dark().palette);
+    }
+
+    #[test]
+    fn unknown_key_is_rejected() {
+        let result = Theme::from_toml("cursor_color = [1.0, 0.0, 0.0]");
+        assert!(matches!(result, Err(ThemeError::Parse(_))));
+    }
+
+    #[test]
+    fn bad_color_is_rejected() {
+        let result = Theme::from_toml("text = [0.5, 0.5]");
+        assert!(matches!(result, Err(ThemeError::Parse(_))));
+    }
+
+    #[test]
+    fn unknown_base_is_rejected() {
+        let result = Theme::from_toml("base = \"solarized\"");
+        assert!(matches!(result, Err(ThemeError::UnknownBase(name)) if name == "solarized"));
+    }
+}
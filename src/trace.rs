@@ -0,0 +1,1243 @@
+use std::cell::OnceCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::axis::AxisType;
+use crate::colormap::Colormap;
+
+/// Whether the x values of a trace are monotonically increasing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum XOrder {
+    /// x is non-decreasing, lookups can use binary search
+    Sorted,
+    /// parametric data (Lissajous curves, hysteresis loops, sweeps with retrace...)
+    Unsorted,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Marker {
+    Circle,
+    Square,
+    Diamond,
+    Cross,
+}
+
+/// Marker appearance of a scatter trace. Size and color can be taken from extra data
+/// channels of the trace.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScatterStyle {
+    pub marker: Marker,
+    /// marker size in pixels, used when there is no size channel
+    pub size: f64,
+    /// channel name, and the marker sizes (pixels) its minimum and maximum are mapped to
+    pub size_by: Option<(String, (f64, f64))>,
+    /// channel name, its values are mapped through the colormap
+    pub color_by: Option<(String, Colormap)>,
+}
+
+impl Default for ScatterStyle {
+    fn default() -> Self {
+        Self {
+            marker: Marker::Circle,
+            size: 6.0,
+            size_by: None,
+            color_by: None,
+        }
+    }
+}
+
+/// Fill appearance of bars and areas
+#[derive(Clone, Debug, PartialEq)]
+pub struct FillStyle {
+    /// fill color, None to use the trace color
+    pub color: Option<(f64, f64, f64)>,
+    pub alpha: f64,
+    /// outline width in pixels, 0 for no outline
+    pub edge_width: f64,
+    /// outline color, None to use the trace color
+    pub edge_color: Option<(f64, f64, f64)>,
+}
+
+impl Default for FillStyle {
+    fn default() -> Self {
+        Self {
+            color: None,
+            alpha: 0.6,
+            edge_width: 1.0,
+            edge_color: None,
+        }
+    }
+}
+
+/// Lower boundary of a filled area
+#[derive(Clone, Debug, PartialEq)]
+pub enum Baseline {
+    /// fill down (or up) to a constant y value
+    Value(f64),
+    /// fill between the trace and these points (e.g. the values of another trace)
+    Trace(Vec<(f64, f64)>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceKind {
+    /// polyline through all points
+    Line,
+    /// markers only
+    Scatter(ScatterStyle),
+    /// one bar per point, centered on x and `width` wide (data units)
+    Bars {
+        width: f64,
+        baseline: f64,
+        fill: FillStyle,
+    },
+    /// line with the region between it and a baseline filled
+    Area { baseline: Baseline, fill: FillStyle },
+}
+
+/// Rule for choosing the number of histogram bins
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Binning {
+    Count(usize),
+    /// ceil(log2(n)) + 1 bins
+    Sturges,
+    /// bin width 2 * IQR / n^(1/3), robust against outliers
+    FreedmanDiaconis,
+}
+
+impl Binning {
+    /// Number of bins for sorted, finite samples
+    fn bin_count(&self, sorted: &[f64]) -> usize {
+        let n = sorted.len();
+        let sturges = (n as f64).log2().ceil() as usize + 1;
+        let bins = match self {
+            Binning::Count(bins) => *bins,
+            Binning::Sturges => sturges,
+            Binning::FreedmanDiaconis => {
+                let quantile = |q: f64| sorted[((n - 1) as f64 * q).round() as usize];
+                let iqr = quantile(0.75) - quantile(0.25);
+                let range = sorted[n - 1] - sorted[0];
+                if iqr > 0.0 {
+                    (range / (2.0 * iqr / (n as f64).cbrt())).ceil() as usize
+                } else {
+                    sturges
+                }
+            }
+        };
+        bins.clamp(1, 10_000)
+    }
+}
+
+/// Uncertainty of each data point
+#[derive(Clone, Debug)]
+pub enum ErrorValues {
+    /// value ± error
+    Symmetric(Vec<f64>),
+    /// (below, above) the value, both positive
+    Asymmetric(Vec<f64>, Vec<f64>),
+}
+
+impl ErrorValues {
+    fn len(&self) -> usize {
+        match self {
+            ErrorValues::Symmetric(e) => e.len(),
+            ErrorValues::Asymmetric(minus, _) => minus.len(),
+        }
+    }
+
+    /// (below, above) extents at point `i`, non-finite errors count as zero
+    pub fn at(&self, i: usize) -> (f64, f64) {
+        let finite_or_zero = |v: f64| if v.is_finite() { v.abs() } else { 0.0 };
+        match self {
+            ErrorValues::Symmetric(e) => (finite_or_zero(e[i]), finite_or_zero(e[i])),
+            ErrorValues::Asymmetric(minus, plus) => {
+                (finite_or_zero(minus[i]), finite_or_zero(plus[i]))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorStyle {
+    /// error bars with caps (`cap` is the cap width in pixels)
+    Bars { cap: f64 },
+    /// translucent band around the line (y errors only)
+    Band { alpha: f64 },
+}
+
+#[derive(Clone, Debug)]
+pub struct Errors {
+    pub x: Option<ErrorValues>,
+    pub y: Option<ErrorValues>,
+    pub style: ErrorStyle,
+}
+
+/// Per-point values (a channel, errors or image cells) whose count doesn't match the data
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LengthError {
+    pub expected: usize,
+    pub found: usize,
+}
+
+impl fmt::Display for LengthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected {} values, got {}", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for LengthError {}
+
+/// Samples were appended to a trace with per-point channels or errors, which they
+/// have no values for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AppendError;
+
+impl fmt::Display for AppendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "can't append samples to a trace with channels or errors")
+    }
+}
+
+impl std::error::Error for AppendError {}
+
+/// Result of snapping the cursor to a trace
+#[derive(Clone, Debug)]
+pub struct SnapPoint {
+    /// index of the trace in its Axes
+    pub trace: usize,
+    /// index of the nearest data point
+    pub index: usize,
+    /// snapped position (on the line between points for line traces)
+    pub x: f64,
+    pub y: f64,
+    /// values of the extra data channels at `index`
+    pub channels: Vec<(String, f64)>,
+    /// (below, above) errors at `index`, if the trace has them
+    pub x_error: Option<(f64, f64)>,
+    pub y_error: Option<(f64, f64)>,
+}
+
+#[derive(Clone)]
+pub struct Trace {
+    /// immutable once shared: clones of a Trace (or of `values` itself) are cheap and can
+    /// be sent to worker threads, changes copy the points if they are still in use there.
+    /// A ring buffer for streaming, kept contiguous so `points` can return one slice.
+    pub values: Arc<VecDeque<(f64, f64)>>,
+    /// bounding box of the finite values, None if there are none
+    pub bbox: Option<gtk::cairo::Rectangle>,
+    pub name: String,
+    pub order: XOrder,
+    pub kind: TraceKind,
+    /// extra per-point data columns (same length as `values`)
+    pub channels: Vec<(String, Vec<f64>)>,
+    pub errors: Option<Errors>,
+    /// maximum number of points kept by `append` (streaming traces), None for unbounded
+    pub capacity: Option<usize>,
+    /// spatial index for snapping, only built for unsorted traces and only when snapping
+    /// (reset whenever the data changes), see `index`
+    index: OnceCell<Option<SegmentGrid>>,
+    /// (x, y) axis types the index is built for
+    scales: (AxisType, AxisType),
+    /// changes whenever the points, channels or errors do, see `revision`
+    revision: u64,
+}
+
+/// Revisions are unique across all traces, so a replaced trace never matches the old one
+fn next_revision() -> u64 {
+    static REVISION: AtomicU64 = AtomicU64::new(0);
+    REVISION.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+impl Trace {
+    /// Create a trace, the x ordering is detected from the data
+    pub fn new(values: Vec<(f64, f64)>, name: &str) -> Self {
+        let order = if sorted_from(None, &values) {
+            XOrder::Sorted
+        } else {
+            XOrder::Unsorted
+        };
+        Self::with_order(values, name, order)
+    }
+
+    /// Create a trace that is treated as parametric even if x happens to be sorted
+    pub fn parametric(values: Vec<(f64, f64)>, name: &str) -> Self {
+        Self::with_order(values, name, XOrder::Unsorted)
+    }
+
+    /// Create a scatter trace (markers only)
+    pub fn scatter(values: Vec<(f64, f64)>, name: &str, style: ScatterStyle) -> Self {
+        Self::new(values, name).with_kind(TraceKind::Scatter(style))
+    }
+
+    /// Create a bar chart trace, bars are 80% of the smallest x spacing wide
+    pub fn bars(values: Vec<(f64, f64)>, name: &str, fill: FillStyle) -> Self {
+        let spacing = values
+            .windows(2)
+            .map(|w| (w[1].0 - w[0].0).abs())
+            .filter(|d| *d > 0.0 && d.is_finite())
+            .fold(f64::INFINITY, f64::min);
+        let width = if spacing.is_finite() {
+            0.8 * spacing
+        } else {
+            1.0
+        };
+        Self::new(values, name).with_kind(TraceKind::Bars {
+            width,
+            baseline: 0.0,
+            fill,
+        })
+    }
+
+    /// Create a histogram of the finite samples, as a bar trace with touching bars
+    pub fn histogram(samples: &[f64], binning: Binning, name: &str, fill: FillStyle) -> Self {
+        let mut sorted: Vec<_> = samples.iter().copied().filter(|v| v.is_finite()).collect();
+        sorted.sort_by(f64::total_cmp);
+        if sorted.is_empty() {
+            return Self::bars(vec![], name, fill);
+        }
+
+        let bins = binning.bin_count(&sorted);
+        let (lo, hi) = (sorted[0], sorted[sorted.len() - 1]);
+        let bin_width = if hi > lo {
+            (hi - lo) / bins as f64
+        } else {
+            1.0
+        };
+
+        let mut counts = vec![0usize; bins];
+        for v in &sorted {
+            // the maximum belongs to the last bin
+            let bin = (((v - lo) / bin_width) as usize).min(bins - 1);
+            counts[bin] += 1;
+        }
+        let values = counts
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (lo + (i as f64 + 0.5) * bin_width, *c as f64))
+            .collect();
+        Self::new(values, name).with_kind(TraceKind::Bars {
+            width: bin_width,
+            baseline: 0.0,
+            fill,
+        })
+    }
+
+    /// Create a trace with the area between it and `baseline` filled
+    pub fn area(values: Vec<(f64, f64)>, name: &str, baseline: Baseline, fill: FillStyle) -> Self {
+        Self::new(values, name).with_kind(TraceKind::Area { baseline, fill })
+    }
+
+    /// Identifies the current data: a new value after every change of the points,
+    /// channels or errors, also for in-place changes of a shared buffer
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// The data points in order
+    pub fn points(&self) -> &[(f64, f64)] {
+        let (points, rest) = self.values.as_slices();
+        debug_assert!(rest.is_empty(), "trace values are kept contiguous");
+        points
+    }
+
+    /// Add y uncertainties (one per point), drawn as error bars unless the style is
+    /// changed
+    pub fn with_y_errors(mut self, errors: ErrorValues) -> Result<Self, LengthError> {
+        self.check_length(errors.len())?;
+        self.errors_mut().y = Some(errors);
+        self.revision = next_revision();
+        self.update_bbox();
+        Ok(self)
+    }
+
+    /// Add x uncertainties (one per point), shown as horizontal error bars
+    pub fn with_x_errors(mut self, errors: ErrorValues) -> Result<Self, LengthError> {
+        self.check_length(errors.len())?;
+        self.errors_mut().x = Some(errors);
+        self.revision = next_revision();
+        self.update_bbox();
+        Ok(self)
+    }
+
+    fn check_length(&self, found: usize) -> Result<(), LengthError> {
+        match self.values.len() {
+            expected if expected != found => Err(LengthError { expected, found }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn with_error_style(mut self, style: ErrorStyle) -> Self {
+        self.errors_mut().style = style;
+        self
+    }
+
+    fn errors_mut(&mut self) -> &mut Errors {
+        self.errors.get_or_insert(Errors {
+            x: None,
+            y: None,
+            style: ErrorStyle::Bars { cap: 6.0 },
+        })
+    }
+
+    /// (x, y) errors at point `index`
+    pub fn errors_at(&self, index: usize) -> (Option<(f64, f64)>, Option<(f64, f64)>) {
+        match &self.errors {
+            Some(e) => (
+                e.x.as_ref().map(|x| x.at(index)),
+                e.y.as_ref().map(|y| y.at(index)),
+            ),
+            None => (None, None),
+        }
+    }
+
+    /// Change how the trace is drawn (the bounding box depends on it)
+    pub fn with_kind(mut self, kind: TraceKind) -> Self {
+        self.kind = kind;
+        self.update_bbox();
+        self.update_index();
+        self
+    }
+
+    /// Add an extra data column (one value per point), e.g. for scatter marker size or
+    /// color
+    pub fn with_channel(mut self, name: &str, values: Vec<f64>) -> Result<Self, LengthError> {
+        self.check_length(values.len())?;
+        self.channels.push((name.to_owned(), values));
+        self.revision = next_revision();
+        Ok(self)
+    }
+
+    pub fn channel(&self, name: &str) -> Option<&[f64]> {
+        self.channels
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| &v[..])
+    }
+
+    /// (min, max) of the finite values in a channel
+    pub fn channel_range(&self, name: &str) -> Option<(f64, f64)> {
+        let (min, max) = self
+            .channel(name)?
+            .iter()
+            .filter(|v| v.is_finite())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+                (min.min(*v), max.max(*v))
+            });
+        (min <= max).then_some((min, max))
+    }
+
+    /// Values of all channels at point `index`
+    pub fn channel_values(&self, index: usize) -> Vec<(String, f64)> {
+        self.channels
+            .iter()
+            .map(|(n, v)| (n.clone(), v[index]))
+            .collect()
+    }
+
+    /// Create an empty line trace for live data, keeping the newest `capacity` points
+    pub fn streaming(name: &str, capacity: usize) -> Self {
+        let mut trace = Self::with_order(vec![], name, XOrder::Sorted);
+        trace.capacity = Some(capacity.max(1));
+        // room for twice the capacity, so the buffer wraps (and `append` has to make it
+        // contiguous again) at most once per `capacity` appended samples
+        Arc::make_mut(&mut trace.values).reserve(2 * capacity.max(1));
+        trace
+    }
+
+    /// Append samples, dropping the oldest ones beyond the capacity (cheap, the points
+    /// are a ring buffer). The bounding box is extended incrementally and only
+    /// recomputed when points on its bounds were dropped. Traces with per-point channels
+    /// or errors can't take samples without them, nothing is appended to them.
+    pub fn append(&mut self, samples: &[(f64, f64)]) -> Result<(), AppendError> {
+        if !self.channels.is_empty() || self.errors.is_some() {
+            return Err(AppendError);
+        }
+        if samples.is_empty() {
+            return Ok(());
+        }
+        let last = self
+            .values
+            .iter()
+            .rev()
+            .map(|(x, _)| *x)
+            .find(|x| x.is_finite());
+        let sorted = self.order == XOrder::Sorted && sorted_from(last, samples);
+
+        self.revision = next_revision();
+        let values = Arc::make_mut(&mut self.values);
+        values.extend(samples);
+        let excess = self
+            .capacity
+            .map_or(0, |capacity| values.len().saturating_sub(capacity));
+        // sorted by x, only the leftmost x bound can be dropped (and is moved below)
+        let on_bounds = |(x, y): (f64, f64)| {
+            self.bbox.is_some_and(|b| {
+                // the far edges (origin plus size) may be off by a rounding error
+                let on = |v: f64, lo: f64, hi: f64| {
+                    let slack = 1e-12 * (lo.abs() + hi.abs());
+                    v <= lo + slack || v >= hi - slack
+                };
+                let on_x = on(x, b.x(), b.x() + b.width());
+                on(y, b.y(), b.y() + b.height()) || (!sorted && on_x)
+            })
+        };
+        let dropped_bounds = values.drain(..excess).any(on_bounds);
+        values.make_contiguous();
+
+        if !sorted {
+            self.order = XOrder::Unsorted;
+            // rebuilt on the next snap
+            self.index = OnceCell::new();
+        }
+        if dropped_bounds || !matches!(self.kind, TraceKind::Line | TraceKind::Scatter(_)) {
+            self.update_bbox();
+            return Ok(());
+        }
+        let mut bbox = self.bbox;
+        if excess > 0 && sorted {
+            // the oldest point left is the leftmost one
+            let first = self.values.iter().find(|p| is_finite(p));
+            bbox = match (bbox, first) {
+                (Some(b), Some((x, _))) => Some(gtk::cairo::Rectangle::new(
+                    *x,
+                    b.y(),
+                    b.x() + b.width() - x,
+                    b.height(),
+                )),
+                _ => None,
+            };
+        }
+        // samples beyond the capacity were dropped right away
+        let kept = &samples[samples.len().saturating_sub(self.values.len())..];
+        let finite = kept.iter().filter(|p| is_finite(p));
+        self.bbox = finite.fold(bbox, |bbox, (x, y)| {
+            Some(match bbox {
+                None => gtk::cairo::Rectangle::new(*x, *y, 0.0, 0.0),
+                Some(b) => {
+                    let (x0, y0) = (b.x().min(*x), b.y().min(*y));
+                    let x1 = (b.x() + b.width()).max(*x);
+                    let y1 = (b.y() + b.height()).max(*y);
+                    gtk::cairo::Rectangle::new(x0, y0, x1 - x0, y1 - y0)
+                }
+            })
+        });
+        Ok(())
+    }
+
+    /// Replace the data points, keeping name and style. Channels and errors are dropped
+    /// if they no longer match the number of points.
+    pub fn set_values(&mut self, values: Vec<(f64, f64)>) {
+        self.values = Arc::new(values.into());
+        self.revision = next_revision();
+        self.channels.retain(|(_, c)| c.len() == self.values.len());
+        if let Some(errors) = &self.errors {
+            if errors
+                .x
+                .iter()
+                .chain(&errors.y)
+                .any(|e| e.len() != self.values.len())
+            {
+                self.errors = None;
+            }
+        }
+        self.update_bbox();
+        self.update_index();
+    }
+
+    /// Set the types of the axes the trace is shown on (for the snapping index and the
+    /// baselines in the data range), `Axes::add_trace` does this
+    pub fn set_scales(&mut self, scales: (AxisType, AxisType)) {
+        if scales != self.scales {
+            self.scales = scales;
+            self.revision = next_revision();
+            self.update_bbox();
+            self.update_index();
+        }
+    }
+
+    fn update_index(&mut self) {
+        self.index = OnceCell::new();
+    }
+
+    /// The snapping index, built on first use
+    fn index(&self) -> Option<&SegmentGrid> {
+        // scatter and bar traces snap to the points only, not the lines between them
+        let points_only = matches!(self.kind, TraceKind::Scatter(_) | TraceKind::Bars { .. });
+        self.index
+            .get_or_init(|| match self.order {
+                XOrder::Unsorted => SegmentGrid::new(self.points(), self.scales, points_only),
+                XOrder::Sorted => None,
+            })
+            .as_ref()
+    }
+
+    fn with_order(values: Vec<(f64, f64)>, name: &str, order: XOrder) -> Self {
+        let mut s = Self {
+            values: Arc::new(values.into()),
+            bbox: None,
+            name: name.to_owned(),
+            order,
+            kind: TraceKind::Line,
+            channels: vec![],
+            errors: None,
+            capacity: None,
+            index: OnceCell::new(),
+            scales: (AxisType::Lin, AxisType::Lin),
+            revision: next_revision(),
+        };
+        s.update_bbox();
+        s.update_index();
+        s
+    }
+
+    fn update_bbox(&mut self) {
+        // error extents are part of the data range
+        let (xmin, xmax, ymin, ymax) = self
+            .values
+            .iter()
+            .enumerate()
+            .filter(|(_, (x, y))| x.is_finite() && y.is_finite())
+            .fold(
+                (
+                    f64::INFINITY,
+                    f64::NEG_INFINITY,
+                    f64::INFINITY,
+                    f64::NEG_INFINITY,
+                ),
+                |(xmin, xmax, ymin, ymax), (i, (x, y))| {
+                    let (x_err, y_err) = self.errors_at(i);
+                    let (x_minus, x_plus) = x_err.unwrap_or((0.0, 0.0));
+                    let (y_minus, y_plus) = y_err.unwrap_or((0.0, 0.0));
+                    (
+                        xmin.min(x - x_minus),
+                        xmax.max(x + x_plus),
+                        ymin.min(y - y_minus),
+                        ymax.max(y + y_plus),
+                    )
+                },
+            );
+        if xmin > xmax {
+            self.bbox = None;
+            return;
+        }
+
+        // bars and filled areas extend beyond the data points
+        let baseline = |b: f64| self.shown_baseline(b).unwrap_or(ymin);
+        let (xmin, xmax, ymin, ymax) = match &self.kind {
+            TraceKind::Line | TraceKind::Scatter(_) => (xmin, xmax, ymin, ymax),
+            TraceKind::Bars {
+                width, baseline: b, ..
+            } => (
+                xmin - width / 2.0,
+                xmax + width / 2.0,
+                ymin.min(baseline(*b)),
+                ymax.max(baseline(*b)),
+            ),
+            TraceKind::Area {
+                baseline: Baseline::Value(b),
+                ..
+            } => (xmin, xmax, ymin.min(baseline(*b)), ymax.max(baseline(*b))),
+            TraceKind::Area {
+                baseline: Baseline::Trace(lower),
+                ..
+            } => lower.iter().filter(|p| is_finite(p)).fold(
+                (xmin, xmax, ymin, ymax),
+                |(xmin, xmax, ymin, ymax), (x, y)| {
+                    (xmin.min(*x), xmax.max(*x), ymin.min(*y), ymax.max(*y))
+                },
+            ),
+        };
+        self.bbox = Some(gtk::cairo::Rectangle::new(
+            xmin,
+            ymin,
+            xmax - xmin,
+            ymax - ymin,
+        ));
+    }
+
+    /// A baseline that is part of the data range, None if the y axis can't show it (zero or
+    /// below on a log axis, where the fill starts at the bottom of the chart)
+    fn shown_baseline(&self, baseline: f64) -> Option<f64> {
+        self.scales
+            .1
+            .data_to_scale(baseline)
+            .is_finite()
+            .then_some(baseline)
+    }
+
+    /// y range of the points with x in `[x0, x1]`, including the baseline of bars and
+    /// filled areas
+    pub fn y_range_within(&self, x0: f64, x1: f64) -> Option<(f64, f64)> {
+        let visible = match self.order {
+            XOrder::Sorted => {
+                let points = self.points();
+                let start = points.partition_point(|(x, _)| *x < x0);
+                let end = points.partition_point(|(x, _)| *x <= x1);
+                &points[start..end.max(start)]
+            }
+            XOrder::Unsorted => self.points(),
+        };
+
+        let (ymin, ymax) = visible
+            .iter()
+            .filter(|(x, y)| *x >= x0 && *x <= x1 && y.is_finite())
+            .fold(
+                (f64::INFINITY, f64::NEG_INFINITY),
+                |(ymin, ymax), (_, y)| (ymin.min(*y), ymax.max(*y)),
+            );
+        if ymin > ymax {
+            return None;
+        }
+        let (ymin, ymax) = match &self.kind {
+            TraceKind::Bars { baseline, .. }
+            | TraceKind::Area {
+                baseline: Baseline::Value(baseline),
+                ..
+            } => match self.shown_baseline(*baseline) {
+                Some(b) => (ymin.min(b), ymax.max(b)),
+                None => (ymin, ymax),
+            },
+            TraceKind::Area {
+                baseline: Baseline::Trace(lower),
+                ..
+            } => lower
+                .iter()
+                .filter(|(x, y)| *x >= x0 && *x <= x1 && y.is_finite())
+                .fold((ymin, ymax), |(ymin, ymax), (_, y)| {
+                    (ymin.min(*y), ymax.max(*y))
+                }),
+            TraceKind::Line | TraceKind::Scatter(_) => (ymin, ymax),
+        };
+        Some((ymin, ymax))
+    }
+
+    /// Point of the trace nearest to (t, y), as (normalized squared distance, index of
+    /// the data point, x, y), None if there is none within the radii. The radii are in
+    /// units of the axis scales (decades on a log axis), so distances follow the ones on
+    /// screen.
+    pub fn nearest_point(
+        &self,
+        t: f64,
+        y: f64,
+        (tradius, yradius): (f64, f64),
+        (x_scale, y_scale): (AxisType, AxisType),
+    ) -> Option<(f64, usize, f64, f64)> {
+        let scaled = |(x, y): (f64, f64)| (x_scale.data_to_scale(x), y_scale.data_to_scale(y));
+        let query = scaled((t, y));
+        let radius = (tradius, yradius);
+
+        // scatter and bar traces snap to the points only, not the lines between them
+        let points_only = matches!(self.kind, TraceKind::Scatter(_) | TraceKind::Bars { .. });
+        let end_for = |i: usize, end: usize| if points_only { i + 1 } else { end };
+        let shown = |i: &usize| is_finite(&scaled(self.values[*i]));
+
+        let distances: Vec<_> = match self.index() {
+            Some(grid) if grid.scales == (x_scale, y_scale) => grid
+                .query(
+                    query.0 - tradius,
+                    query.0 + tradius,
+                    query.1 - yradius,
+                    query.1 + yradius,
+                )
+                .into_iter()
+                .map(|i| {
+                    let end = end_for(i, self.values.len());
+                    self.segment_distance(i, end, query, radius, scaled)
+                })
+                .collect(),
+            // index built for other axis types
+            Some(_) => (0..self.values.len())
+                .filter(shown)
+                .map(|i| {
+                    let end = end_for(i, self.values.len());
+                    self.segment_distance(i, end, query, radius, scaled)
+                })
+                .collect(),
+            None => {
+                let t0 = x_scale.scale_to_data(query.0 - tradius);
+                let t1 = x_scale.scale_to_data(query.0 + tradius);
+                let segment_start = self
+                    .values
+                    .partition_point(|(x, _)| *x < t0)
+                    .saturating_sub(2);
+                let segment_end =
+                    (self.values.partition_point(|(x, _)| *x < t1) + 2).min(self.values.len());
+
+                (segment_start..segment_end)
+                    .filter(shown)
+                    .map(|i| {
+                        let end = end_for(i, segment_end);
+                        self.segment_distance(i, end, query, radius, scaled)
+                    })
+                    .collect()
+            }
+        };
+
+        distances
+            .into_iter()
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .filter(|(d, _, _, _)| *d < 1.0)
+            .map(|(d, i, x, y)| (d, i, x_scale.scale_to_data(x), y_scale.scale_to_data(y)))
+    }
+
+    /// Normalized squared distance from `query` to the segment starting at point `i`, the
+    /// index of the closer end point, and the nearest point on the segment, all in scale
+    /// units. A point without a successor that can be shown (before `end`) is treated as
+    /// a zero-length segment.
+    fn segment_distance(
+        &self,
+        i: usize,
+        end: usize,
+        (t, y): (f64, f64),
+        (tradius, yradius): (f64, f64),
+        scaled: impl Fn((f64, f64)) -> (f64, f64),
+    ) -> (f64, usize, f64, f64) {
+        let p0 = scaled(self.values[i]);
+        let p1 = match self.values.get(i + 1).map(|p| scaled(*p)) {
+            Some(p) if i + 1 < end && is_finite(&p) => p,
+            _ => p0,
+        };
+
+        // normalized query point
+        let t_norm = t / tradius;
+        let y_norm = y / yradius;
+
+        // normalized start point and segment vector:
+        let start_x = p0.0 / tradius;
+        let start_y = p0.1 / yradius;
+        let dx = (p1.0 - p0.0) / tradius;
+        let dy = (p1.1 - p0.1) / yradius;
+
+        // length squared of segment:
+        let l2 = dx * dx + dy * dy;
+
+        // normalized projection onto the segment
+        // (value between 0 and 1 means the projection lies on the segment)
+        let proj = if l2 > 0.0 {
+            ((t_norm - start_x) * dx + (y_norm - start_y) * dy) / l2
+        } else {
+            0.0
+        };
+        let proj = proj.clamp(0.0, 1.0);
+
+        let nearest_x = start_x + proj * dx;
+        let nearest_y = start_y + proj * dy;
+
+        let dist_x = nearest_x - t_norm;
+        let dist_y = nearest_y - y_norm;
+
+        let distance = dist_x * dist_x + dist_y * dist_y;
+
+        let index = if proj > 0.5 { i + 1 } else { i };
+
+        (distance, index, nearest_x * tradius, nearest_y * yradius)
+    }
+}
+
+/// Whether the finite x values are non-decreasing, starting from `last`. Non-finite x
+/// (gaps) are skipped, so a NaN can't hide a step back.
+fn sorted_from(last: Option<f64>, values: &[(f64, f64)]) -> bool {
+    let mut finite = values.iter().map(|(x, _)| *x).filter(|x| x.is_finite());
+    let Some(first) = last.or_else(|| finite.next()) else {
+        return true;
+    };
+    finite
+        .try_fold(first, |last, x| (x >= last).then_some(x))
+        .is_some()
+}
+
+fn is_finite((x, y): &(f64, f64)) -> bool {
+    x.is_finite() && y.is_finite()
+}
+
+/// Uniform grid over the trace's bounding box on the axis scales, so the cells are
+/// uniform on screen for log axes too. Each cell lists the segments that cross it (by
+/// index of the segment's start point), or the points in it for traces that only snap
+/// to their points.
+#[derive(Clone)]
+struct SegmentGrid {
+    scales: (AxisType, AxisType),
+    origin: (f64, f64),
+    cell_size: (f64, f64),
+    dims: (usize, usize),
+    cells: Vec<Vec<u32>>,
+}
+
+impl SegmentGrid {
+    /// None if no point can be shown on these axis types
+    fn new(
+        values: &[(f64, f64)],
+        (x_scale, y_scale): (AxisType, AxisType),
+        points_only: bool,
+    ) -> Option<Self> {
+        let values: Vec<_> = values
+            .iter()
+            .map(|(x, y)| (x_scale.data_to_scale(*x), y_scale.data_to_scale(*y)))
+            .collect();
+        let (xmin, xmax, ymin, ymax) = values.iter().filter(|p| is_finite(p)).fold(
+            (
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+            ),
+            |(xmin, xmax, ymin, ymax), (x, y)| {
+                (xmin.min(*x), xmax.max(*x), ymin.min(*y), ymax.max(*y))
+            },
+        );
+        if xmin > xmax {
+            return None;
+        }
+
+        // aim for a handful of segments per cell
+        let n = ((values.len() as f64 / 4.0).sqrt().ceil() as usize).clamp(1, 256);
+        let mut grid = Self {
+            scales: (x_scale, y_scale),
+            origin: (xmin, ymin),
+            cell_size: (
+                ((xmax - xmin) / n as f64).max(f64::MIN_POSITIVE),
+                ((ymax - ymin) / n as f64).max(f64::MIN_POSITIVE),
+            ),
+            dims: (n, n),
+            cells: vec![vec![]; n * n],
+        };
+
+        for (i, &p0) in values.iter().enumerate() {
+            if !is_finite(&p0) {
+                continue;
+            }
+            let p1 = match values.get(i + 1) {
+                Some(p) if !points_only && is_finite(p) => *p,
+                _ => p0,
+            };
+            // a segment ending in a corner can reach a cell twice
+            grid.walk(p0, p1, |cell| {
+                if cell.last() != Some(&(i as u32)) {
+                    cell.push(i as u32);
+                }
+            });
+        }
+        Some(grid)
+    }
+
+    /// Cell of a point, clamped to the grid
+    fn cell(&self, (x, y): (f64, f64)) -> (usize, usize) {
+        (self.cell_span_x(x, x).0, self.cell_span_y(y, y).0)
+    }
+
+    /// Visit the cells the segment from `p0` to `p1` crosses, in order (a 2D DDA). Where
+    /// the segment passes a cell corner, both cells next to it are visited too.
+    fn walk(&mut self, p0: (f64, f64), p1: (f64, f64), mut visit: impl FnMut(&mut Vec<u32>)) {
+        let (mut cx, mut cy) = self.cell(p0);
+        let end = self.cell(p1);
+        let (dx, dy) = (p1.0 - p0.0, p1.1 - p0.1);
+        let step = |d: f64| if d > 0.0 { 1 } else { -1 };
+        // segment parameter t (0 at p0, 1 at p1) of the next cell boundary in x and y,
+        // and the change of t from one boundary to the next
+        let first = |d: f64, c: usize, origin: f64, size: f64, p: f64| {
+            if d == 0.0 {
+                return (f64::INFINITY, f64::INFINITY);
+            }
+            let boundary = origin + (c as f64 + if d > 0.0 { 1.0 } else { 0.0 }) * size;
+            ((boundary - p) / d, size / d.abs())
+        };
+        let (mut tx, delta_x) = first(dx, cx, self.origin.0, self.cell_size.0, p0.0);
+        let (mut ty, delta_y) = first(dy, cy, self.origin.1, self.cell_size.1, p0.1);
+
+        let (w, h) = (self.dims.0 as isize, self.dims.1 as isize);
+        let mut visit_at = |grid: &mut Self, x: isize, y: isize| {
+            if (0..w).contains(&x) && (0..h).contains(&y) {
+                visit(&mut grid.cells[y as usize * grid.dims.0 + x as usize]);
+            }
+        };
+        // rounding can't make the walk longer than the grid is wide and high
+        for _ in 0..=(self.dims.0 + self.dims.1) {
+            visit_at(self, cx as isize, cy as isize);
+            if (cx, cy) == end || tx.min(ty) > 1.0 {
+                break;
+            }
+            let (nx, ny) = (cx as isize + step(dx), cy as isize + step(dy));
+            if (tx - ty).abs() <= 1e-9 * tx.abs().max(1.0) {
+                visit_at(self, nx, cy as isize);
+                visit_at(self, cx as isize, ny);
+                tx += delta_x;
+                ty += delta_y;
+                (cx, cy) = (nx.clamp(0, w - 1) as usize, ny.clamp(0, h - 1) as usize);
+            } else if tx < ty {
+                tx += delta_x;
+                cx = nx.clamp(0, w - 1) as usize;
+            } else {
+                ty += delta_y;
+                cy = ny.clamp(0, h - 1) as usize;
+            }
+        }
+        visit_at(self, end.0 as isize, end.1 as isize);
+    }
+
+    fn cell_span_x(&self, x0: f64, x1: f64) -> (usize, usize) {
+        let max = self.dims.0 as f64 - 1.0;
+        let c0 = ((x0 - self.origin.0) / self.cell_size.0)
+            .floor()
+            .clamp(0.0, max);
+        let c1 = ((x1 - self.origin.0) / self.cell_size.0)
+            .floor()
+            .clamp(0.0, max);
+        (c0 as usize, c1 as usize)
+    }
+
+    fn cell_span_y(&self, y0: f64, y1: f64) -> (usize, usize) {
+        let max = self.dims.1 as f64 - 1.0;
+        let c0 = ((y0 - self.origin.1) / self.cell_size.1)
+            .floor()
+            .clamp(0.0, max);
+        let c1 = ((y1 - self.origin.1) / self.cell_size.1)
+            .floor()
+            .clamp(0.0, max);
+        (c0 as usize, c1 as usize)
+    }
+
+    /// Indices of all segments that may intersect the given box (sorted, unique)
+    fn query(&self, x0: f64, x1: f64, y0: f64, y1: f64) -> Vec<usize> {
+        let (cx0, cx1) = self.cell_span_x(x0, x1);
+        let (cy0, cy1) = self.cell_span_y(y0, y1);
+        let mut result: Vec<usize> = (cy0..=cy1)
+            .flat_map(|cy| (cx0..=cx1).map(move |cx| cy * self.dims.0 + cx))
+            .flat_map(|c| self.cells[c].iter().map(|i| *i as usize))
+            .collect();
+        result.sort_unstable();
+        result.dedup();
+        result
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    /// Mostly finite, sometimes NaN or infinite
+    fn random_value(rng: &mut StdRng) -> f64 {
+        match rng.gen_range(0..20) {
+            0 => f64::NAN,
+            1 => f64::INFINITY,
+            2 => f64::NEG_INFINITY,
+            _ => rng.gen_range(-100.0..100.0),
+        }
+    }
+
+    /// Empty, single-point or longer data with repeated points (zero-length segments),
+    /// sorted by x or not
+    fn random_values(rng: &mut StdRng) -> Vec<(f64, f64)> {
+        let n = match rng.gen_range(0..4) {
+            0 => 0,
+            1 => 1,
+            _ => rng.gen_range(2..200),
+        };
+        let mut values = Vec::with_capacity(n);
+        while values.len() < n {
+            let p = (random_value(rng), random_value(rng));
+            values.push(p);
+            if rng.gen_bool(0.1) {
+                values.push(p);
+            }
+        }
+        if rng.gen_bool(0.5) {
+            values.sort_by(|a, b| a.0.total_cmp(&b.0));
+        }
+        values
+    }
+
+    pub(crate) fn random_trace(rng: &mut StdRng) -> Trace {
+        let values = random_values(rng);
+        let n = values.len();
+        let trace = match rng.gen_range(0..6) {
+            0 => Trace::new(values, "line"),
+            1 => Trace::parametric(values, "parametric"),
+            2 => Trace::scatter(values, "scatter", ScatterStyle::default()),
+            3 => Trace::bars(values, "bars", FillStyle::default()),
+            4 => Trace::area(values, "area", Baseline::Value(0.0), FillStyle::default()),
+            _ => {
+                let lower = random_values(rng);
+                Trace::area(values, "band", Baseline::Trace(lower), FillStyle::default())
+            }
+        };
+        if rng.gen_bool(0.3) {
+            let errors = (0..n).map(|_| random_value(rng)).collect();
+            trace.with_y_errors(ErrorValues::Symmetric(errors)).unwrap()
+        } else {
+            trace
+        }
+    }
+
+    fn bbox_is_finite(trace: &Trace) -> bool {
+        match trace.bbox {
+            Some(b) => [b.x(), b.y(), b.width(), b.height()]
+                .iter()
+                .all(|v| v.is_finite()),
+            None => true,
+        }
+    }
+
+    #[test]
+    fn bbox_is_none_or_finite() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..500 {
+            let trace = random_trace(&mut rng);
+            assert!(bbox_is_finite(&trace), "{:?}", trace.values);
+            let has_finite = trace.values.iter().any(is_finite);
+            assert_eq!(trace.bbox.is_some(), has_finite, "{:?}", trace.values);
+        }
+    }
+
+    #[test]
+    fn appended_bbox_is_none_or_finite() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..50 {
+            let mut trace = Trace::streaming("live", rng.gen_range(1..100));
+            for _ in 0..20 {
+                trace.append(&random_values(&mut rng)).unwrap();
+                assert!(bbox_is_finite(&trace), "{:?}", trace.values);
+                // the incrementally updated box is the one computed from scratch
+                let mut recomputed = trace.clone();
+                recomputed.update_bbox();
+                let corners = |b: Option<gtk::cairo::Rectangle>| {
+                    b.map(|b| [b.x(), b.y(), b.x() + b.width(), b.y() + b.height()])
+                };
+                match (corners(trace.bbox), corners(recomputed.bbox)) {
+                    (Some(a), Some(b)) => {
+                        assert!(
+                            a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-9),
+                            "{a:?} {b:?}"
+                        )
+                    }
+                    (a, b) => assert_eq!(a, b),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn append_keeps_per_point_values() {
+        let style = ScatterStyle::default();
+        let mut trace = Trace::scatter(vec![(0.0, 1.0)], "t", style)
+            .with_channel("size", vec![3.0])
+            .unwrap();
+        assert_eq!(trace.append(&[(1.0, 2.0)]), Err(AppendError));
+        assert_eq!(trace.points(), &[(0.0, 1.0)]);
+        assert_eq!(trace.channel("size"), Some(&[3.0][..]));
+    }
+
+    #[test]
+    fn streaming_keeps_newest_points() {
+        let mut trace = Trace::streaming("live", 10);
+        for start in (0..100).step_by(7) {
+            let samples: Vec<_> = (start..start + 7).map(|i| (i as f64, 0.0)).collect();
+            trace.append(&samples).unwrap();
+        }
+        let expected: Vec<_> = (95..105).map(|i| (i as f64, 0.0)).collect();
+        assert_eq!(trace.points(), &expected[..]);
+        assert_eq!(trace.bbox.map(|b| (b.x(), b.width())), Some((95.0, 9.0)));
+    }
+
+    #[test]
+    fn grid_lists_segments_where_they_cross() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let values: Vec<_> = (0..3000)
+            .map(|_| (rng.gen_range(-100.0..100.0), rng.gen_range(0.0..50.0)))
+            .collect();
+        let trace = Trace::parametric(values.clone(), "random walk");
+        let grid = trace.index().unwrap();
+        // each segment is in the cells along it, not in all cells of its bounding box
+        let entries: usize = grid.cells.iter().map(Vec::len).sum();
+        assert!(entries <= values.len() * 2 * (grid.dims.0 + grid.dims.1 + 2));
+        for (i, w) in values.windows(2).enumerate() {
+            for t in [0.0, 0.3, 0.5, 0.9, 1.0] {
+                let (x, y) = (
+                    w[0].0 + (w[1].0 - w[0].0) * t,
+                    w[0].1 + (w[1].1 - w[0].1) * t,
+                );
+                assert!(grid.query(x, x, y, y).contains(&i), "segment {i} at {t}");
+            }
+        }
+
+        let scatter = trace.with_kind(TraceKind::Scatter(ScatterStyle::default()));
+        let grid = scatter.index().unwrap();
+        assert_eq!(grid.cells.iter().map(Vec::len).sum::<usize>(), values.len());
+    }
+
+    #[test]
+    fn nan_gap_does_not_hide_unsorted_x() {
+        let values = vec![(0.0, 1.0), (f64::NAN, 2.0), (-5.0, 3.0)];
+        assert_eq!(Trace::new(values, "t").order, XOrder::Unsorted);
+        let values = vec![
+            (0.0, 1.0),
+            (f64::NAN, 2.0),
+            (5.0, 3.0),
+            (f64::INFINITY, 0.0),
+        ];
+        assert_eq!(Trace::new(values, "t").order, XOrder::Sorted);
+
+        let mut trace = Trace::streaming("live", 10);
+        trace.append(&[(1.0, 0.0), (f64::NAN, 0.0)]).unwrap();
+        assert_eq!(trace.order, XOrder::Sorted);
+        trace.append(&[(0.5, 0.0)]).unwrap();
+        assert_eq!(trace.order, XOrder::Unsorted);
+    }
+
+    #[test]
+    fn full_ring_buffer_changes_revision() {
+        let mut trace = Trace::streaming("live", 4);
+        trace
+            .append(&[(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0)])
+            .unwrap();
+        let revision = trace.revision();
+        let len = trace.values.len();
+        trace.append(&[(4.0, 1.0)]).unwrap();
+        assert_eq!(trace.values.len(), len);
+        assert_ne!(trace.revision(), revision);
+    }
+
+    #[test]
+    fn per_point_values_must_match() {
+        let trace = Trace::new(vec![(0.0, 1.0), (1.0, 2.0)], "t");
+        let error = LengthError {
+            expected: 2,
+            found: 3,
+        };
+        let channel = trace.clone().with_channel("c", vec![0.0; 3]);
+        assert_eq!(channel.err(), Some(error));
+        let errors = trace
+            .clone()
+            .with_x_errors(ErrorValues::Symmetric(vec![0.1; 3]));
+        assert_eq!(errors.err(), Some(error));
+        assert!(trace.with_channel("c", vec![0.0; 2]).is_ok());
+    }
+
+    #[test]
+    fn nearest_point_is_finite() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let axis_type = |rng: &mut StdRng| match rng.gen_bool(0.5) {
+            true => AxisType::Lin,
+            false => AxisType::Log,
+        };
+        for _ in 0..500 {
+            let mut trace = random_trace(&mut rng);
+            // the index is built for these, sometimes snapping uses other ones
+            trace.set_scales((axis_type(&mut rng), axis_type(&mut rng)));
+            for _ in 0..20 {
+                let (t, y) = (rng.gen_range(-150.0..150.0), rng.gen_range(-150.0..150.0));
+                let radius = (rng.gen_range(0.01..50.0), rng.gen_range(0.01..50.0));
+                let scales = match rng.gen_bool(0.8) {
+                    true => trace.scales,
+                    false => (axis_type(&mut rng), axis_type(&mut rng)),
+                };
+                if let Some((d, index, x, y)) = trace.nearest_point(t, y, radius, scales) {
+                    assert!(d.is_finite() && x.is_finite() && y.is_finite());
+                    assert!(index < trace.values.len());
+                }
+            }
+        }
+    }
+}
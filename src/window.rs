@@ -3,15 +3,23 @@ use adw::prelude::*;
 use gtk::prelude::{BoxExt, ButtonExt, GtkWindowExt, OrientableExt};
 use relm4::{
     gtk, Component, ComponentController, ComponentParts, ComponentSender, Controller, RelmApp,
-    SimpleComponent,
+    RelmWidgetExt, SimpleComponent,
 };
 
+use crate::eye::EyeMeasurements;
+use crate::measure::Measurements;
+use crate::plot::demo::pulse_axes;
 use crate::plot_component::*;
+use crate::spectrum::SpectrumMetrics;
 
 struct AppModel {
     sidebar: bool,
     counter: u8,
     plot: Controller<PlotModel>,
+    /// measurement table shown in the sidebar
+    measurements: String,
+    /// shows errors
+    toasts: adw::ToastOverlay,
 }
 
 #[derive(Debug)]
@@ -19,6 +27,31 @@ enum AppMsg {
     Increment,
     ShowSidebar,
     HideSidebar,
+    Measurements(Vec<(String, Measurements)>),
+    Spectrum(SpectrumMetrics),
+    Eye(EyeMeasurements),
+    Error(String),
+}
+
+/// Aligned "name   value" lines
+fn table_rows(rows: &[(&str, String)]) -> String {
+    let rows: Vec<_> = rows
+        .iter()
+        .map(|(label, value)| format!("{label:<11}{value:>12}"))
+        .collect();
+    rows.join("\n")
+}
+
+/// Format measurement results as a text table, one block per trace
+fn measurement_table(results: &[(String, Measurements)]) -> String {
+    if results.is_empty() {
+        return "No measurements".to_owned();
+    }
+    results
+        .iter()
+        .map(|(name, m)| format!("{name}\n{}", table_rows(&m.table())))
+        .collect::<Vec<_>>()
+        .join("\n\n")
 }
 
 #[derive(Debug)]
@@ -36,54 +69,63 @@ impl SimpleComponent for AppModel {
             set_default_width: 800,
             set_default_height: 500,
 
-            adw::ToolbarView {
-                add_top_bar = &adw::HeaderBar {
-                    #[wrap(Some)]
-                    set_title_widget = &adw::WindowTitle {
-                        set_title: "My HeaderBar",
-                    },
+            #[name = "toasts"]
+            adw::ToastOverlay {
+                #[wrap(Some)]
+                set_child = &adw::ToolbarView {
+                    add_top_bar = &adw::HeaderBar {
+                        #[wrap(Some)]
+                        set_title_widget = &adw::WindowTitle {
+                            set_title: "My HeaderBar",
+                        },
 
-                    pack_start = &gtk::ToggleButton {
-                        set_icon_name: "dock-left",
-                        set_active: true,
-                        connect_toggled[sender] => move |btn| {
-                            let action = if btn.is_active() { AppMsg::ShowSidebar } else {AppMsg::HideSidebar};
-                            sender.input(action)
+                        pack_start = &gtk::ToggleButton {
+                            set_icon_name: "dock-left",
+                            set_active: true,
+                            connect_toggled[sender] => move |btn| {
+                                let action = if btn.is_active() { AppMsg::ShowSidebar } else {AppMsg::HideSidebar};
+                                sender.input(action)
+                            },
                         },
-                    },
 
-                    pack_end = &gtk::MenuButton {
-                        set_icon_name: "menu",
+                        pack_end = &gtk::MenuButton {
+                            set_icon_name: "menu",
+                        },
                     },
-                },
-                #[wrap(Some)]
-                set_content = &adw::NavigationSplitView {
-                    set_show_content: true,
-                    #[watch]
-                    set_collapsed: !model.sidebar,
                     #[wrap(Some)]
-                    set_sidebar = &adw::NavigationPage {
-                        set_title: "the sidebar",
-                        gtk::Box {
-                            set_orientation: gtk::Orientation::Vertical,
-                            set_hexpand: true,
-                            set_spacing: 12,
-
-                            gtk::Label {
-                                set_label: "asdf"
+                    set_content = &adw::NavigationSplitView {
+                        set_show_content: true,
+                        #[watch]
+                        set_collapsed: !model.sidebar,
+                        #[wrap(Some)]
+                        set_sidebar = &adw::NavigationPage {
+                            set_title: "the sidebar",
+                            gtk::Box {
+                                set_orientation: gtk::Orientation::Vertical,
+                                set_hexpand: true,
+                                set_spacing: 12,
+
+                                gtk::Label {
+                                    add_css_class: "monospace",
+                                    set_margin_all: 12,
+                                    set_xalign: 0.0,
+                                    set_selectable: true,
+                                    #[watch]
+                                    set_label: &model.measurements,
+                                }
                             }
-                        }
-                    },
-                    #[wrap(Some)]
-                    set_content = &adw::NavigationPage {
-                        set_title: "the content",
-                        gtk::Box {
-                            set_orientation: gtk::Orientation::Vertical,
-                            set_vexpand: true,
-                            set_spacing: 12,
-                            append = model.plot.widget(),
-                        }
-                    },
+                        },
+                        #[wrap(Some)]
+                        set_content = &adw::NavigationPage {
+                            set_title: "the content",
+                            gtk::Box {
+                                set_orientation: gtk::Orientation::Vertical,
+                                set_vexpand: true,
+                                set_spacing: 12,
+                                append = model.plot.widget(),
+                            }
+                        },
+                    }
                 }
             }
         }
@@ -96,18 +138,27 @@ impl SimpleComponent for AppModel {
         sender: ComponentSender<Self>,
     ) -> ComponentParts<Self> {
         let plot: Controller<PlotModel> =
-            PlotModel::builder().launch(()).forward(sender.input_sender(), |msg| match msg {
-                PlotOutput::Close => AppMsg::Increment,
-            });
-
-        let model = AppModel {
+            PlotModel::builder()
+                .launch(vec![pulse_axes()])
+                .forward(sender.input_sender(), |msg| match msg {
+                    PlotOutput::Close => AppMsg::Increment,
+                    PlotOutput::Measurements(results) => AppMsg::Measurements(results),
+                    PlotOutput::Spectrum(metrics) => AppMsg::Spectrum(metrics),
+                    PlotOutput::Eye(measurements) => AppMsg::Eye(measurements),
+                    PlotOutput::Error(message) => AppMsg::Error(message),
+                });
+
+        let mut model = AppModel {
             counter,
             plot,
             sidebar: true,
+            measurements: measurement_table(&[]),
+            toasts: adw::ToastOverlay::new(),
         };
 
         // Insert the macro code generation here
         let widgets = view_output!();
+        model.toasts = widgets.toasts.clone();
 
         ComponentParts { model, widgets }
     }
@@ -119,6 +170,14 @@ impl SimpleComponent for AppModel {
             }
             AppMsg::ShowSidebar => self.sidebar = true,
             AppMsg::HideSidebar => self.sidebar = false,
+            AppMsg::Measurements(results) => self.measurements = measurement_table(&results),
+            AppMsg::Spectrum(metrics) => {
+                self.measurements = format!("Spectrum\n{}", table_rows(&metrics.table()))
+            }
+            AppMsg::Eye(measurements) => {
+                self.measurements = format!("Eye\n{}", table_rows(&measurements.table()))
+            }
+            AppMsg::Error(message) => self.toasts.add_toast(adw::Toast::new(&message)),
         }
     }
 }